@@ -0,0 +1,220 @@
+use crate::commands::DEFAULT_COMMENT;
+use crate::hostsfile::{LineError, ManagedHostsFile};
+use crate::metadata;
+
+/// One line of a batch file.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Operation {
+    Add {
+        ip: String,
+        names: Vec<String>,
+        comment: String,
+        update: bool,
+    },
+    Remove {
+        host: String,
+    },
+    Disable {
+        host: String,
+    },
+    Enable {
+        host: String,
+    },
+    Toggle {
+        host: String,
+    },
+}
+
+/// Parses every operation in `contents`, skipping blank lines and lines
+/// starting with `#`.
+pub fn parse(contents: &str) -> Result<Vec<(usize, Operation)>, LineError> {
+    let mut operations = vec![];
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match parse_line(line) {
+            Ok(op) => operations.push((i + 1, op)),
+            Err(message) => {
+                return Err(LineError {
+                    line: i + 1,
+                    message,
+                })
+            }
+        }
+    }
+    Ok(operations)
+}
+
+fn parse_line(line: &str) -> Result<Operation, String> {
+    let (command, comment) = match line.find('#') {
+        Some(i) => (&line[..i], line[i + 1..].trim()),
+        None => (line, ""),
+    };
+    let mut words = command.split_whitespace();
+    let name = words.next().unwrap_or_default();
+    let mut args = words.collect::<Vec<&str>>();
+    let update =
+        matches!(name, "add" | "local") && matches!(args.first(), Some(&"-u") | Some(&"--update"));
+    if update {
+        args.remove(0);
+    }
+    let host = |args: &[&str]| match args {
+        [host] => Ok(String::from(*host)),
+        _ => Err(format!("`{}` expects exactly one host", name)),
+    };
+    match name {
+        "add" | "local" => {
+            let (ip, rest) = match (name, args.as_slice()) {
+                ("local", [names, rest @ ..]) => ("127.0.0.1", (*names, rest)),
+                ("add", [ip, names, rest @ ..]) => (*ip, (*names, rest)),
+                _ => return Err(format!("usage: {} [-u] <ip> <names> [comment]", name)),
+            };
+            let (names, extra) = rest;
+            let comment = extra
+                .iter()
+                .copied()
+                .chain(Some(comment).filter(|c| !c.is_empty()))
+                .collect::<Vec<&str>>()
+                .join(" ");
+            Ok(Operation::Add {
+                ip: String::from(ip),
+                names: names
+                    .split(',')
+                    .filter(|n| !n.is_empty())
+                    .map(String::from)
+                    .collect(),
+                comment: if comment.is_empty() {
+                    String::from(DEFAULT_COMMENT)
+                } else {
+                    comment
+                },
+                update,
+            })
+        }
+        "remove" | "rm" => Ok(Operation::Remove { host: host(&args)? }),
+        "disable" | "dis" => Ok(Operation::Disable { host: host(&args)? }),
+        "enable" | "en" => Ok(Operation::Enable { host: host(&args)? }),
+        "toggle" => Ok(Operation::Toggle { host: host(&args)? }),
+        _ => Err(format!("unknown operation `{}`", name)),
+    }
+}
+
+/// Applies one operation, failing where the matching command would refuse.
+/// Returns a description of the change.
+pub fn apply(hosts_file: &mut ManagedHostsFile, op: &Operation) -> Result<String, String> {
+    match op {
+        Operation::Add {
+            ip,
+            names,
+            comment,
+            update,
+        } => {
+            for name in names {
+                if !update && hosts_file.has_host(name) {
+                    return Err(format!("{} is already in hosts file", name));
+                }
+                while hosts_file.has_host(name) {
+                    hosts_file.remove_host(name);
+                }
+            }
+            let comment = metadata::with_managed_tag(comment);
+            hosts_file.put_entry(None, ip, names, Some(format!("# {}", comment)))?;
+            Ok(format!("Adding {} {}", ip, names.join(" ")))
+        }
+        Operation::Remove { host } => {
+            if !hosts_file.has_host(host) {
+                return Err(format!("{} is not in hosts file", host));
+            }
+            hosts_file.remove_host(host);
+            Ok(format!("Removing host {}", host))
+        }
+        Operation::Disable { host } => {
+            if !hosts_file.has_host(host) {
+                return Err(format!("{} is not enabled in hosts file", host));
+            }
+            hosts_file.disable_host(host);
+            Ok(format!("Disabling host {}", host))
+        }
+        Operation::Enable { host } => {
+            if !hosts_file.has_disabled_host(host) {
+                return Err(format!("{} is not disabled in hosts file", host));
+            }
+            hosts_file.enable_host(host);
+            Ok(format!("Enabling host {}", host))
+        }
+        Operation::Toggle { host } => {
+            if hosts_file.toggle_host(host) == 0 {
+                return Err(format!("{} is not in hosts file", host));
+            }
+            Ok(format!("Toggling host {}", host))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_operations() {
+        let contents = "# setup\n\nadd 10.0.0.5 a.test,b.test # my app\nlocal -u c.test dev\nrm x.test\ndisable y.test\n";
+        assert_eq!(
+            parse(contents).unwrap(),
+            vec![
+                (
+                    3,
+                    Operation::Add {
+                        ip: String::from("10.0.0.5"),
+                        names: vec![String::from("a.test"), String::from("b.test")],
+                        comment: String::from("my app"),
+                        update: false,
+                    }
+                ),
+                (
+                    4,
+                    Operation::Add {
+                        ip: String::from("127.0.0.1"),
+                        names: vec![String::from("c.test")],
+                        comment: String::from("dev"),
+                        update: true,
+                    }
+                ),
+                (
+                    5,
+                    Operation::Remove {
+                        host: String::from("x.test")
+                    }
+                ),
+                (
+                    6,
+                    Operation::Disable {
+                        host: String::from("y.test")
+                    }
+                ),
+            ]
+        );
+        assert_eq!(parse("add 10.0.0.5").unwrap_err().line, 1);
+        assert_eq!(parse("\nrm a b").unwrap_err().line, 2);
+        assert_eq!(parse("frobnicate a").unwrap_err().line, 1);
+    }
+
+    #[test]
+    fn apply_operations() {
+        let mut hf =
+            ManagedHostsFile::from_string("127.0.0.1 localhost\n10.0.0.1 x.test\n", "test");
+        let ops = parse("add 10.0.0.5 a.test\ndisable x.test\n").unwrap();
+        for (_, op) in &ops {
+            apply(&mut hf, op).unwrap();
+        }
+        assert_eq!(
+            hf.contents(),
+            "127.0.0.1 localhost\n#10.0.0.1 x.test\n10.0.0.5 a.test # Added by hostman\n"
+        );
+        let (_, op) = &parse("add 10.0.0.6 a.test").unwrap()[0];
+        assert!(apply(&mut hf, op).is_err());
+        let (_, op) = &parse("rm missing.test").unwrap()[0];
+        assert!(apply(&mut hf, op).is_err());
+    }
+}
@@ -0,0 +1,143 @@
+use crate::hostsfile::ManagedHostsFile;
+use crate::metadata::{self, get_tag};
+use crate::schedule::{Schedule, SCHEDULE_TAG};
+use chrono::{DateTime, NaiveDateTime, Utc};
+
+pub const BLOCK_TAG: &str = "block";
+pub const BLOCK_COMMENT: &str = "Blocked by hostman";
+const BLOCKED_IPS: &[&str] = &["0.0.0.0", "::"];
+const SUBDOMAINS: &[&str] = &["www", "m"];
+
+/// Returns the names blocked for a domain: the domain itself and its common
+/// subdomains.
+pub fn names(domain: &str) -> Vec<String> {
+    let domain = domain.trim_end_matches('.').to_lowercase();
+    let base = SUBDOMAINS
+        .iter()
+        .find_map(|s| domain.strip_prefix(&format!("{}.", s)))
+        .unwrap_or(&domain);
+    std::iter::once(String::from(base))
+        .chain(SUBDOMAINS.iter().map(|s| format!("{}.{}", s, base)))
+        .collect()
+}
+
+/// Adds the IPv4 and IPv6 block lines of a domain, replacing any earlier block
+/// of it. Lines with a schedule start disabled when it is not active at `now`.
+/// Fails if another line already has one of the names.
+pub fn add(
+    hosts_file: &mut ManagedHostsFile,
+    domain: &str,
+    expires: Option<DateTime<Utc>>,
+    schedule: Option<Schedule>,
+    now: NaiveDateTime,
+) -> Result<Vec<String>, String> {
+    let names = names(domain);
+    let base = &names[0];
+    let taken = names
+        .iter()
+        .filter(|n| {
+            hosts_file.find_entries(n).into_iter().any(|i| {
+                let comment = hosts_file.line(i).comment().unwrap_or_default();
+                get_tag(&comment, BLOCK_TAG).as_ref() != Some(base)
+            })
+        })
+        .cloned()
+        .collect::<Vec<String>>();
+    if !taken.is_empty() {
+        return Err(format!("already in hosts file: {}", taken.join(" ")));
+    }
+    hosts_file.remove_tagged(BLOCK_TAG, base);
+
+    let mut comment = metadata::with_tag(BLOCK_COMMENT, BLOCK_TAG, base);
+    if let Some(t) = expires {
+        comment = metadata::with_expiry(&comment, t);
+    }
+    if let Some(s) = schedule {
+        comment = metadata::with_tag(&comment, SCHEDULE_TAG, &s.to_string());
+    }
+    for ip in BLOCKED_IPS {
+        let index = hosts_file.put_entry(None, ip, &names, Some(format!("# {}", comment)))?;
+        if schedule.is_some_and(|s| !s.is_active(now)) {
+            hosts_file.toggle_at(index);
+        }
+    }
+    Ok(names)
+}
+
+/// Removes the block lines of a domain, returning how many there were.
+pub fn remove(hosts_file: &mut ManagedHostsFile, domain: &str) -> usize {
+    hosts_file.remove_tagged(BLOCK_TAG, &names(domain)[0])
+}
+
+/// Enables the lines whose schedule is active at `now` and disables the others,
+/// returning how many lines changed.
+pub fn apply_schedules(hosts_file: &mut ManagedHostsFile, now: NaiveDateTime) -> usize {
+    let mut changed = 0;
+    for (index, enabled) in hosts_file.entries() {
+        let comment = hosts_file.entry_at(index).and_then(|l| l.comment());
+        let schedule = comment
+            .and_then(|c| get_tag(&c, SCHEDULE_TAG))
+            .and_then(|s| s.parse::<Schedule>().ok());
+        if schedule.is_some_and(|s| s.is_active(now) != enabled) {
+            hosts_file.toggle_at(index);
+            changed += 1;
+        }
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn blocked_names() {
+        assert_eq!(
+            names("YouTube.com."),
+            vec!["youtube.com", "www.youtube.com", "m.youtube.com"]
+        );
+        assert_eq!(names("www.example.test")[0], "example.test");
+    }
+
+    #[test]
+    fn add_and_remove() {
+        let mut hf = ManagedHostsFile::from_string("127.0.0.1 localhost\n", "test");
+        let now = at("2026-10-16 12:00");
+        add(&mut hf, "example.test", None, None, now).unwrap();
+        add(&mut hf, "www.example.test", None, None, now).unwrap();
+        assert_eq!(
+            hf.contents(),
+            "127.0.0.1 localhost\n\
+             0.0.0.0 example.test www.example.test m.example.test # Blocked by hostman [block=example.test]\n\
+             :: example.test www.example.test m.example.test # Blocked by hostman [block=example.test]\n"
+        );
+        assert_eq!(remove(&mut hf, "example.test"), 2);
+
+        hf.add_line("10.0.0.1 m.example.test");
+        assert!(add(&mut hf, "example.test", None, None, now).is_err());
+    }
+
+    #[test]
+    fn scheduled_blocks() {
+        let mut hf = ManagedHostsFile::from_string("", "test");
+        let work = "weekdays 09:00-17:00".parse::<Schedule>().ok();
+        add(&mut hf, "example.test", None, work, at("2026-10-17 12:00")).unwrap();
+        assert!(!hf.has_host("example.test"));
+        assert_eq!(apply_schedules(&mut hf, at("2026-10-19 09:30")), 2);
+        assert!(hf.has_host("example.test"));
+        assert_eq!(apply_schedules(&mut hf, at("2026-10-19 10:00")), 0);
+        assert_eq!(apply_schedules(&mut hf, at("2026-10-19 17:00")), 2);
+        assert!(hf.contents().starts_with(
+            "#0.0.0.0 example.test www.example.test m.example.test # Blocked by hostman [block=example.test] [schedule=weekdays 09:00-17:00]\n"
+        ));
+
+        let contents = format!("127.0.0.1\tlocalhost\n{}", hf.contents());
+        let mut hf = ManagedHostsFile::from_string(&contents, "test");
+        assert_eq!(apply_schedules(&mut hf, at("2026-10-19 18:00")), 0);
+        assert!(hf.is_unchanged());
+    }
+}
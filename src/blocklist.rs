@@ -0,0 +1,268 @@
+use crate::hostsfile::ManagedHostsFile;
+use crate::metadata::{self, SOURCE_TAG};
+use hosts_parser::HostsFileLine;
+use std::collections::HashSet;
+use std::io::Read;
+use std::net::IpAddr;
+
+const SECTION_PREFIX: &str = "hostman blocklist ";
+const BLOCKED_IP: &str = "0.0.0.0";
+/// Names that hosts-format lists define for the system itself.
+const RESERVED: &[&str] = &[
+    "localhost",
+    "localhost.localdomain",
+    "local",
+    "broadcasthost",
+    "ip6-localhost",
+    "ip6-loopback",
+    "ip6-localnet",
+    "ip6-mcastprefix",
+    "ip6-allnodes",
+    "ip6-allrouters",
+    "ip6-allhosts",
+    "0.0.0.0",
+];
+
+/// A blocklist subscription, stored as a section of the hosts file.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Blocklist {
+    pub name: String,
+    pub source: String,
+    pub domains: Vec<String>,
+}
+
+fn section_name(name: &str) -> String {
+    format!("{}{}", SECTION_PREFIX, name)
+}
+
+/// Whether a section of the hosts file holds a blocklist.
+pub fn is_section(section: &str) -> bool {
+    section.starts_with(SECTION_PREFIX)
+}
+
+/// Returns the subscribed lists in file order.
+pub fn lists(hosts_file: &ManagedHostsFile) -> Vec<Blocklist> {
+    hosts_file
+        .sections()
+        .into_iter()
+        .filter_map(|(section, marker)| {
+            let name = section.strip_prefix(SECTION_PREFIX)?;
+            let range = hosts_file.section(&section)?;
+            Some(Blocklist {
+                name: String::from(name),
+                source: metadata::get_tag(&marker, SOURCE_TAG).unwrap_or_default(),
+                domains: hosts_file.lines()[range]
+                    .iter()
+                    .flat_map(|l| l.hosts())
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+/// Reads a list from an `http(s)://` or `file://` url, or a local path.
+pub fn fetch(source: &str) -> Result<String, String> {
+    if source.starts_with("http://") || source.starts_with("https://") {
+        let response = ureq::get(source).call().map_err(|e| e.to_string())?;
+        let mut text = String::new();
+        response
+            .into_reader()
+            .read_to_string(&mut text)
+            .map_err(|e| e.to_string())?;
+        return Ok(text);
+    }
+    let path = source.strip_prefix("file://").unwrap_or(source);
+    std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))
+}
+
+/// Returns the names a hosts-format list blocks, that is the names of its
+/// entries for unspecified or loopback addresses, without the names of the
+/// system itself.
+pub fn parse(contents: &str) -> Vec<String> {
+    let mut domains = vec![];
+    for line in contents.lines() {
+        let line = match HostsFileLine::from_string(line) {
+            Ok(line) if line.has_host() => line,
+            _ => continue,
+        };
+        let blocking = match line.ip().and_then(|ip| ip.parse::<IpAddr>().ok()) {
+            Some(ip) => ip.is_unspecified() || ip.is_loopback(),
+            None => false,
+        };
+        if blocking {
+            domains.extend(
+                line.hosts()
+                    .into_iter()
+                    .map(|h| h.to_lowercase())
+                    .filter(|h| !RESERVED.contains(&h.as_str())),
+            );
+        }
+    }
+    domains
+}
+
+pub fn allowlist_path(file_name: &str) -> String {
+    format!("{}.allowlist", file_name)
+}
+
+/// Reads the allowlist, one name or `*.domain` per line.
+pub fn load_allowlist(file_name: &str) -> Vec<String> {
+    std::fs::read_to_string(allowlist_path(file_name))
+        .unwrap_or_default()
+        .lines()
+        .map(|l| {
+            l.split('#')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_lowercase()
+        })
+        .filter(|l| !l.is_empty())
+        .collect()
+}
+
+pub fn save_allowlist(file_name: &str, allowlist: &[String]) -> Result<(), std::io::Error> {
+    let mut contents = allowlist.join("\n");
+    contents.push('\n');
+    std::fs::write(allowlist_path(file_name), contents)
+}
+
+/// Whether the name is allowed, exactly or through a `*.domain` pattern.
+pub fn is_allowed(allowlist: &[String], name: &str) -> bool {
+    allowlist
+        .iter()
+        .any(|allowed| match allowed.strip_prefix("*.") {
+            Some(domain) => name == domain || name.ends_with(&format!(".{}", domain)),
+            None => name == allowed,
+        })
+}
+
+/// Writes the section of a list, adding it at the end of the file if it is new.
+pub fn put(hosts_file: &mut ManagedHostsFile, list: &Blocklist) {
+    let lines = list
+        .domains
+        .iter()
+        .map(|d| HostsFileLine::from_string(&format!("{} {}", BLOCKED_IP, d)).unwrap())
+        .collect();
+    let tags = metadata::with_tag("", SOURCE_TAG, &list.source);
+    hosts_file.put_section(&section_name(&list.name), &tags, lines);
+}
+
+pub fn remove(hosts_file: &mut ManagedHostsFile, name: &str) -> bool {
+    hosts_file.remove_section(&section_name(name))
+}
+
+/// Rewrites every list, taking the domains of `fetched` lists from there and
+/// keeping the current domains of the others. Allowed names, names the file
+/// defines outside the lists and names of an earlier list are left out.
+/// Returns how many domains each list blocks.
+pub fn rebuild(
+    hosts_file: &mut ManagedHostsFile,
+    fetched: &[(String, Vec<String>)],
+    allowlist: &[String],
+) -> Vec<(String, usize)> {
+    let lists = lists(hosts_file);
+    let sections = lists
+        .iter()
+        .filter_map(|l| hosts_file.section(&section_name(&l.name)))
+        .collect::<Vec<_>>();
+    let mut seen: HashSet<String> = hosts_file
+        .lines()
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !sections.iter().any(|s| s.contains(i)))
+        .flat_map(|(_, l)| l.hosts())
+        .map(|h| h.to_lowercase())
+        .collect();
+    let mut counts = vec![];
+    for mut list in lists {
+        if let Some((_, domains)) = fetched.iter().find(|(name, _)| *name == list.name) {
+            list.domains = domains.clone();
+        }
+        list.domains
+            .retain(|d| !is_allowed(allowlist, d) && seen.insert(d.clone()));
+        counts.push((list.name.clone(), list.domains.len()));
+        put(hosts_file, &list);
+    }
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIST: &str = "\
+# Title: ads
+127.0.0.1 localhost
+::1 localhost ip6-localhost
+255.255.255.255 broadcasthost
+0.0.0.0 ads.example tracker.example # trackers
+127.0.0.1 Metrics.example
+10.0.0.1 intranet.example
+";
+
+    #[test]
+    fn parse_hosts_list() {
+        assert_eq!(
+            parse(LIST),
+            vec!["ads.example", "tracker.example", "metrics.example"]
+        );
+    }
+
+    #[test]
+    fn allowlist_patterns() {
+        let allowlist = vec![String::from("ads.example"), String::from("*.cdn.example")];
+        assert!(is_allowed(&allowlist, "ads.example"));
+        assert!(!is_allowed(&allowlist, "www.ads.example"));
+        assert!(is_allowed(&allowlist, "cdn.example"));
+        assert!(is_allowed(&allowlist, "img.cdn.example"));
+        assert!(!is_allowed(&allowlist, "notcdn.example"));
+    }
+
+    #[test]
+    fn rebuild_dedupes_lists() {
+        let mut hf =
+            ManagedHostsFile::from_string("127.0.0.1 localhost\n10.0.0.5 Mine.example\n", "test");
+        let list = |name: &str, domains: &[&str]| Blocklist {
+            name: String::from(name),
+            source: format!("file:///{}.txt", name),
+            domains: domains.iter().map(|d| String::from(*d)).collect(),
+        };
+        put(&mut hf, &list("ads", &[]));
+        put(&mut hf, &list("more", &["old.example"]));
+        let fetched = vec![(
+            String::from("ads"),
+            parse(LIST)
+                .into_iter()
+                .chain(vec![String::from("mine.example")])
+                .collect(),
+        )];
+        let allowlist = vec![String::from("metrics.example")];
+        assert_eq!(
+            rebuild(&mut hf, &fetched, &allowlist),
+            vec![(String::from("ads"), 2), (String::from("more"), 1)]
+        );
+        assert_eq!(
+            hf.contents(),
+            "127.0.0.1 localhost\n10.0.0.5 Mine.example\n\n\
+             # BEGIN hostman blocklist ads [source=file:///ads.txt]\n\
+             0.0.0.0 ads.example\n0.0.0.0 tracker.example\n\
+             # END hostman blocklist ads\n\n\
+             # BEGIN hostman blocklist more [source=file:///more.txt]\n\
+             0.0.0.0 old.example\n\
+             # END hostman blocklist more\n"
+        );
+
+        let fetched = vec![(String::from("more"), vec![String::from("ads.example")])];
+        assert_eq!(
+            rebuild(&mut hf, &fetched, &[])[1],
+            (String::from("more"), 0)
+        );
+        assert_eq!(
+            lists(&hf)[0].domains,
+            vec!["ads.example", "tracker.example"]
+        );
+        assert!(remove(&mut hf, "ads"));
+        assert_eq!(lists(&hf).len(), 1);
+    }
+}
@@ -1,3 +1,5 @@
+use crate::entry::{Conflict, Format};
+use crate::hostsfile::SortOrder;
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
@@ -18,6 +20,9 @@ pub enum CliCmd {
   Show {
     #[structopt(long = "summary", short = "s")]
     summary: bool,
+    /// Only show hosts whose comment contains this text
+    #[structopt(long = "comment", short = "c")]
+    comment: Option<String>,
   },
 
   #[structopt(name = "local", alias = "l")]
@@ -30,6 +35,44 @@ pub enum CliCmd {
   /// Add host to /etc/hosts (alias: a).
   Add(CmdAdd),
 
+  #[structopt(name = "alias")]
+  #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
+  /// Add or remove aliases on existing hosts.
+  Alias(CmdAlias),
+
+  #[structopt(name = "move", alias = "set-ip")]
+  #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
+  /// Point a host to a new ip, keeping its line in place (alias: set-ip).
+  Move {
+    host: String,
+    ip: String,
+    /// Move only this host to a new line, leaving other names on the old one
+    #[structopt(long = "split", short = "s")]
+    split: bool,
+  },
+
+  #[structopt(name = "rename")]
+  #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
+  /// Rename a host, or every host with a suffix, keeping ips and comments.
+  Rename {
+    #[structopt(required_unless = "suffix")]
+    old: Option<String>,
+    #[structopt(required_unless = "suffix")]
+    new: Option<String>,
+    /// Replace a suffix in every host name, e.g. .dev=.test
+    #[structopt(long = "suffix", parse(try_from_str = parse_suffix), conflicts_with = "old")]
+    suffix: Option<(String, String)>,
+  },
+
+  #[structopt(name = "comment")]
+  #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
+  /// Show or set the comment on the lines of a host.
+  Comment {
+    host: String,
+    /// New comment, leave empty to show the current one
+    text: Vec<String>,
+  },
+
   #[structopt(name = "remove", alias = "rm")]
   #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
   /// Remove host from /etc/hosts (alias: rm).
@@ -45,6 +88,12 @@ pub enum CliCmd {
   /// Enable previously disabled host (alias: en).
   Enable { host: String },
 
+  #[structopt(name = "toggle")]
+  #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
+  /// Enable disabled and disable enabled lines of a host, or pick them from a
+  /// list when no host is given.
+  Toggle { host: Option<String> },
+
   #[structopt(name = "check", alias = "c")]
   #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
   /// Check whether host is in hosts file (alias: c).
@@ -54,6 +103,168 @@ pub enum CliCmd {
     exact: bool,
   },
 
+  #[structopt(name = "export")]
+  #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
+  /// Export entries as json, yaml, toml or csv, as dnsmasq, unbound or
+  /// coredns resolver config, as a BIND zone file, or for docker, compose and
+  /// kubernetes.
+  Export {
+    #[structopt(long = "format", short = "f", default_value = "json", possible_values = &["json", "yaml", "toml", "csv", "dnsmasq", "unbound", "coredns", "zone", "docker", "compose", "k8s"])]
+    format: Format,
+    /// Only export entries whose ip, names or comment contain this text
+    #[structopt(long = "filter")]
+    filter: Option<String>,
+    /// Only export entries matching this host, like `check` does
+    #[structopt(long = "host")]
+    host: Option<String>,
+    /// Only match the exact host name (with --host)
+    #[structopt(long = "exact", short = "e", requires = "host")]
+    exact: bool,
+    /// Also export localhost and the other names of the system
+    #[structopt(long = "all")]
+    all: bool,
+    /// Also resolve subdomains of each name (dnsmasq and unbound only)
+    #[structopt(long = "wildcard")]
+    wildcard: bool,
+    /// Write to this file instead of stdout
+    #[structopt(long = "output", short = "o")]
+    output: Option<String>,
+    /// Domain of the zone file (zone only)
+    #[structopt(long = "origin", required_if("format", "zone"))]
+    origin: Option<String>,
+    /// Name server of the zone, defaults to ns.<origin> (zone only)
+    #[structopt(long = "nameserver", requires = "origin")]
+    nameserver: Option<String>,
+    /// Also write reverse zones with PTR records (zone only)
+    #[structopt(long = "ptr", requires = "origin")]
+    ptr: bool,
+  },
+
+  #[structopt(name = "import")]
+  #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
+  #[structopt(setting = structopt::clap::AppSettings::SubcommandsNegateReqs)]
+  #[structopt(usage = "hostman import [OPTIONS] <file>\n    hostman import [OPTIONS] <SUBCOMMAND>")]
+  /// Add entries from a json, yaml, toml or csv file, or the host names
+  /// declared in compose files or kubernetes manifests.
+  Import {
+    /// File to import, or - for stdin. Pass `-- compose` to import a file named
+    /// like a subcommand.
+    #[structopt(set = structopt::clap::ArgSettings::Required)]
+    file: Option<String>,
+    /// Defaults to the file extension
+    #[structopt(long = "format", short = "f", possible_values = &["json", "yaml", "toml", "csv"])]
+    format: Option<Format>,
+    #[structopt(flatten)]
+    options: ImportOptions,
+    #[structopt(subcommand)]
+    source: Option<CmdImport>,
+  },
+
+  #[structopt(name = "batch")]
+  #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
+  /// Apply add, rm, disable, enable and toggle lines from a file or stdin in a
+  /// single save. Nothing is saved if any line fails.
+  Batch {
+    /// File with one operation per line, or - for stdin (default)
+    file: Option<String>,
+  },
+
+  #[structopt(name = "blocklist")]
+  #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
+  /// Subscribe to hosts-format blocklists.
+  Blocklist(CmdBlocklist),
+
+  #[structopt(name = "block")]
+  #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
+  /// Block domains and their www. and m. subdomains, for a while or on a schedule.
+  Block(CmdBlock),
+
+  #[structopt(name = "unblock")]
+  /// Remove the blocks of domains.
+  Unblock {
+    #[structopt(required = true)]
+    domains: Vec<String>,
+  },
+
+  #[structopt(name = "daemon")]
+  #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
+  /// Keep running, removing expired lines, applying block schedules and
+  /// restoring hostman's lines when other programs rewrite the hosts file.
+  Daemon {
+    /// How often to check the hosts file when it does not change
+    #[structopt(long = "interval", default_value = "30s", parse(try_from_str = humantime::parse_duration))]
+    interval: std::time::Duration,
+    /// How long the hosts file must be left alone before changes are checked
+    #[structopt(long = "debounce", default_value = "500ms", parse(try_from_str = humantime::parse_duration))]
+    debounce: std::time::Duration,
+    /// File with more lines to keep in the hosts file, in the hosts file format
+    #[structopt(long = "enforce", short = "e")]
+    enforce: Option<String>,
+    #[structopt(subcommand)]
+    cmd: Option<CmdDaemon>,
+  },
+
+  #[structopt(name = "tui")]
+  #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
+  /// Browse and edit the hosts file in a terminal UI.
+  Tui {},
+
+  #[structopt(name = "prune")]
+  #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
+  /// Remove hosts whose expiry time has passed.
+  Prune {
+    /// Disable expired hosts instead of removing them
+    #[structopt(long = "disable")]
+    disable: bool,
+  },
+
+  #[structopt(name = "run")]
+  #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
+  /// Add hosts while a command runs, removing them when it exits.
+  Run(CmdRun),
+
+  #[structopt(name = "undo")]
+  /// Undo the last change made by hostman.
+  Undo {},
+
+  #[structopt(name = "redo")]
+  /// Redo the last change undone by hostman.
+  Redo {},
+
+  #[structopt(name = "edit")]
+  /// Edit the hosts file with $VISUAL or $EDITOR, checking it before saving.
+  Edit {},
+
+  #[structopt(name = "dedupe")]
+  #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
+  /// Remove duplicate lines and merge hosts that share an ip into one line.
+  Dedupe {
+    /// Maximum number of aliases per merged line
+    #[structopt(long = "max-aliases")]
+    max_aliases: Option<usize>,
+  },
+
+  #[structopt(name = "fmt")]
+  #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
+  /// Align the hosts file in columns, optionally sorting entries.
+  Fmt {
+    /// Sort entries within each section by ip or name
+    #[structopt(long = "sort", possible_values = &["ip", "name"])]
+    sort: Option<SortOrder>,
+    /// Exit with an error if the file is not formatted, without changing it
+    #[structopt(long = "check")]
+    check: bool,
+  },
+
+  #[structopt(name = "history")]
+  #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
+  /// List recorded changes, optionally only those touching a host.
+  History { host: Option<String> },
+
+  #[structopt(name = "blame")]
+  /// Show hosts annotated with the change that last wrote each line.
+  Blame {},
+
   #[structopt(name = "update", alias = "up")]
   /// Update hostman.
   Update {},
@@ -64,6 +275,15 @@ pub struct CmdAddLocal {
   /// Update host if it already exists in the hosts file
   #[structopt(long = "update", short = "u")]
   pub update: bool,
+  /// Add names to an existing line with the same ip instead of a new line
+  #[structopt(long = "merge", short = "m", conflicts_with_all = &["ttl", "until"])]
+  pub merge: bool,
+  /// Remove the host after this long, e.g. 2h or 1d
+  #[structopt(long = "ttl", parse(try_from_str = humantime::parse_duration), conflicts_with = "until")]
+  pub ttl: Option<std::time::Duration>,
+  /// Remove the host after this time, e.g. "2026-10-19 18:00"
+  #[structopt(long = "until", parse(try_from_str = crate::metadata::parse_timestamp))]
+  pub until: Option<chrono::DateTime<chrono::Utc>>,
   /// Host names to add, comma separated
   pub names: String,
   /// Optional comment
@@ -75,6 +295,15 @@ pub struct CmdAdd {
   /// Update host if it already exists in the hosts file
   #[structopt(long = "update", short = "u")]
   pub update: bool,
+  /// Add names to an existing line with the same ip instead of a new line
+  #[structopt(long = "merge", short = "m", conflicts_with_all = &["ttl", "until"])]
+  pub merge: bool,
+  /// Remove the host after this long, e.g. 2h or 1d
+  #[structopt(long = "ttl", parse(try_from_str = humantime::parse_duration), conflicts_with = "until")]
+  pub ttl: Option<std::time::Duration>,
+  /// Remove the host after this time, e.g. "2026-10-19 18:00"
+  #[structopt(long = "until", parse(try_from_str = crate::metadata::parse_timestamp))]
+  pub until: Option<chrono::DateTime<chrono::Utc>>,
   /// IP Address of the line to add
   pub ip: String,
   /// Host names to add, comma separated
@@ -82,3 +311,183 @@ pub struct CmdAdd {
   /// Optional comment
   pub comment: Vec<String>,
 }
+
+#[derive(Debug, StructOpt)]
+pub struct CmdBlock {
+  /// Unblock after this long, e.g. 2h
+  #[structopt(long = "for", parse(try_from_str = humantime::parse_duration), conflicts_with = "until")]
+  pub duration: Option<std::time::Duration>,
+  /// Unblock at this time, e.g. "2026-10-19 18:00"
+  #[structopt(long = "until", parse(try_from_str = crate::metadata::parse_timestamp))]
+  pub until: Option<chrono::DateTime<chrono::Utc>>,
+  /// Only block on weekdays between these times, e.g. 09:00-17:00
+  #[structopt(long = "weekdays", parse(try_from_str = crate::schedule::parse_range), conflicts_with_all = &["weekends", "daily"])]
+  pub weekdays: Option<(chrono::NaiveTime, chrono::NaiveTime)>,
+  /// Only block on weekends between these times
+  #[structopt(long = "weekends", parse(try_from_str = crate::schedule::parse_range), conflicts_with = "daily")]
+  pub weekends: Option<(chrono::NaiveTime, chrono::NaiveTime)>,
+  /// Only block every day between these times
+  #[structopt(long = "daily", parse(try_from_str = crate::schedule::parse_range))]
+  pub daily: Option<(chrono::NaiveTime, chrono::NaiveTime)>,
+  #[structopt(required = true)]
+  pub domains: Vec<String>,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct ImportOptions {
+  /// What to do with entries whose names are already in the hosts file [default:
+  /// fail]
+  #[structopt(long = "on-conflict", possible_values = &["skip", "overwrite", "fail"])]
+  pub on_conflict: Option<Conflict>,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum CmdImport {
+  #[structopt(name = "compose")]
+  #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
+  /// Add the extra_hosts, hostname and VIRTUAL_HOST or Traefik Host() names of
+  /// a docker-compose file.
+  Compose {
+    file: String,
+    /// Address the names point to
+    #[structopt(long = "ip", default_value = "127.0.0.1")]
+    ip: String,
+  },
+
+  #[structopt(name = "k8s", alias = "kubernetes")]
+  #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
+  /// Add the hosts of the Ingress resources in kubernetes manifests.
+  K8s {
+    #[structopt(required = true)]
+    manifests: Vec<String>,
+    /// Address the names point to
+    #[structopt(long = "ip", default_value = "127.0.0.1")]
+    ip: String,
+  },
+
+  #[structopt(name = "leases")]
+  #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
+  /// Add the host names of dnsmasq, ISC dhcpd or libvirt DHCP leases, replacing
+  /// the addresses of earlier imports from the same file.
+  Leases {
+    #[structopt(required = true)]
+    files: Vec<String>,
+    /// Domain to append to the lease host names, e.g. lab
+    #[structopt(long = "domain")]
+    domain: Option<String>,
+  },
+
+  #[structopt(name = "nginx")]
+  #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
+  /// Add or update the server_name hosts of nginx configs.
+  Nginx {
+    #[structopt(required = true)]
+    files: Vec<String>,
+    /// Address the names point to
+    #[structopt(long = "ip", default_value = "127.0.0.1")]
+    ip: String,
+  },
+
+  #[structopt(name = "apache")]
+  #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
+  /// Add or update the ServerName and ServerAlias hosts of Apache configs.
+  Apache {
+    #[structopt(required = true)]
+    files: Vec<String>,
+    /// Address the names point to
+    #[structopt(long = "ip", default_value = "127.0.0.1")]
+    ip: String,
+  },
+
+  #[structopt(name = "caddy")]
+  #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
+  /// Add or update the site addresses of Caddyfiles.
+  Caddy {
+    #[structopt(required = true)]
+    files: Vec<String>,
+    /// Address the names point to
+    #[structopt(long = "ip", default_value = "127.0.0.1")]
+    ip: String,
+  },
+
+  /// Arguments after the file, which clap takes for an unknown subcommand.
+  #[structopt(external_subcommand)]
+  Extra(Vec<String>),
+}
+
+#[derive(Debug, StructOpt)]
+pub enum CmdAlias {
+  #[structopt(name = "add")]
+  /// Add aliases, comma separated, to the lines of an existing host.
+  Add { host: String, names: String },
+
+  #[structopt(name = "rm", alias = "remove")]
+  /// Remove an alias from the lines of a host.
+  Remove { host: String, alias: String },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum CmdBlocklist {
+  #[structopt(name = "add")]
+  /// Subscribe to a list from an http(s) or file:// url or a path, and block its domains.
+  Add { name: String, source: String },
+
+  #[structopt(name = "update")]
+  /// Fetch the lists again, all of them or only the named one.
+  Update { name: Option<String> },
+
+  #[structopt(name = "rm", alias = "remove")]
+  /// Unsubscribe from a list and unblock its domains.
+  Remove { name: String },
+
+  #[structopt(name = "ls", alias = "list")]
+  /// Show the lists and the allowlist.
+  List {},
+
+  #[structopt(name = "allow")]
+  /// Never block these names; *.domain allows a domain and its subdomains.
+  Allow {
+    /// Take the names off the allowlist instead
+    #[structopt(long = "remove", short = "r")]
+    remove: bool,
+    #[structopt(required = true)]
+    names: Vec<String>,
+  },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum CmdDaemon {
+  #[structopt(name = "status")]
+  /// Show whether the daemon is running and which of its lines are missing.
+  Status {},
+}
+
+#[derive(Debug, StructOpt)]
+pub struct CmdRun {
+  /// Temporary host as ip=names (names comma separated), can be repeated
+  #[structopt(long = "add", short = "a", number_of_values = 1, parse(try_from_str = parse_ip_names))]
+  pub add: Vec<(String, String)>,
+  /// Command to run, after --
+  #[structopt(last = true, required = true)]
+  pub command: Vec<String>,
+}
+
+fn parse_suffix(value: &str) -> Result<(String, String), String> {
+  let mut parts = value.splitn(2, '=');
+  match (parts.next(), parts.next()) {
+    (Some(old), Some(new)) if !old.is_empty() && !new.is_empty() => {
+      Ok((String::from(old), String::from(new)))
+    }
+    _ => Err(format!("expected old=new, got {}", value)),
+  }
+}
+
+fn parse_ip_names(value: &str) -> Result<(String, String), String> {
+  let mut parts = value.splitn(2, '=');
+  match (parts.next(), parts.next()) {
+    (Some(ip), Some(names)) if !ip.is_empty() && !names.is_empty() => {
+      Ok((String::from(ip), String::from(names)))
+    }
+    _ => Err(format!("expected ip=names, got {}", value)),
+  }
+}
@@ -1,18 +1,51 @@
+use crate::batch;
+use crate::block;
+use crate::blocklist;
 use crate::cli::*;
-use crate::hostsfile::{ManagedHostsFile, MatchType};
+use crate::daemon;
+use crate::diff;
+use crate::drift;
+use crate::entry::{self, Conflict, Entry, Format};
+use crate::hostsfile::{
+    self, ConflictError, FormatOptions, ManagedHostsFile, MatchType, SortOrder,
+};
+use crate::journal::{self, Action, Record};
+use crate::leases;
+use crate::manifests;
+use crate::metadata;
+use crate::picker;
+use crate::schedule::{Days, Schedule};
+use crate::tui;
+use crate::vhosts::{self, Vhosts};
+use crate::zone;
+use chrono::{Local, Utc};
 use colored::*;
 use hosts_parser::HostsFileLine;
+use std::io::Write;
+use std::process::{Command, ExitStatus};
 
-pub fn show(summary: bool) {
+pub const DEFAULT_COMMENT: &str = "Added by hostman";
+const RUN_TAG: &str = "run";
+const CONFLICT_EXIT_CODE: i32 = 3;
+
+pub fn show(summary: bool, comment: Option<&str>) {
     let hosts_file = ManagedHostsFile::must_load();
     if summary {
         println!("{}", hosts_file);
     } else {
+        let comment = comment.map(|c| c.to_lowercase());
         println!(
             "{}",
             hosts_file
                 .without_comments()
                 .iter()
+                .filter(|l| match &comment {
+                    Some(pattern) => l
+                        .comment()
+                        .map(|c| metadata::text(&c).to_lowercase().contains(pattern))
+                        .unwrap_or(false),
+                    None => true,
+                })
                 .map(|l| format_line(l))
                 .collect::<Vec<String>>()
                 .join("\n")
@@ -20,12 +53,36 @@ pub fn show(summary: bool) {
     }
 }
 fn format_line(l: &HostsFileLine) -> String {
+    let line = format_line_parts(l);
+    match l.comment().and_then(|c| metadata::expires(&c)) {
+        Some(t) => format!("{} {}", line, format_expiry(t)),
+        None => line,
+    }
+}
+
+fn format_expiry(expires: chrono::DateTime<Utc>) -> ColoredString {
+    let remaining = (expires - Utc::now()).num_seconds();
+    if remaining <= 0 {
+        "(expired)".red()
+    } else {
+        let remaining = remaining as u64;
+        let remaining = if remaining > 60 {
+            remaining - remaining % 60
+        } else {
+            remaining
+        };
+        let remaining = humantime::format_duration(std::time::Duration::from_secs(remaining));
+        format!("(expires in {})", remaining).magenta()
+    }
+}
+
+fn format_line_parts(l: &HostsFileLine) -> String {
     if l.has_host() && l.has_comment() {
         format!(
             "{} {} {} {}",
             l.ip().unwrap().as_str().blue(),
             l.hosts().first().unwrap().as_str().green(),
-            (&l.hosts()[1..]).join(" ").as_str().yellow(),
+            l.hosts()[1..].join(" ").as_str().yellow(),
             l.comment().unwrap().cyan()
         )
     } else if l.has_host() {
@@ -33,7 +90,7 @@ fn format_line(l: &HostsFileLine) -> String {
             "{} {} {}",
             l.ip().unwrap().as_str().blue(),
             l.hosts().first().unwrap().as_str().green(),
-            (&l.hosts()[1..]).join(" ").as_str().yellow(),
+            l.hosts()[1..].join(" ").as_str().yellow(),
         )
     } else {
         format!("{}", l.comment().unwrap().as_str().cyan())
@@ -60,25 +117,65 @@ pub fn add(args: &Cli, sub_cmd: &CmdAdd) {
         ip,
         comment,
         update,
+        merge,
+        ttl,
+        until,
     } = sub_cmd;
     let all_names = names.split(',').collect::<Vec<&str>>();
-    let mut hosts_file = ManagedHostsFile::must_load();
-    let matches = hosts_file.get_multi_match(&all_names, &MatchType::Exact);
+    let mut hosts_file = ManagedHostsFile::must_load_locked();
+    let comment = comment.join(" ");
+    let computed_comment = if comment.is_empty() {
+        DEFAULT_COMMENT
+    } else {
+        &comment
+    };
+    let expires = expiry_or_exit(*ttl, *until);
+    let computed_comment = match expires {
+        Some(t) => metadata::with_expiry(computed_comment, t),
+        None => String::from(computed_comment),
+    };
+    if add_names(
+        &mut hosts_file,
+        ip,
+        &all_names,
+        &computed_comment,
+        *update,
+        *merge,
+    ) {
+        maybe_save(args.dry_run, hosts_file);
+    }
+}
+
+fn expiry_or_exit(
+    ttl: Option<std::time::Duration>,
+    until: Option<chrono::DateTime<Utc>>,
+) -> Option<chrono::DateTime<Utc>> {
+    metadata::new_expiry(ttl, until, Utc::now()).unwrap_or_else(|err| {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    })
+}
+
+/// Adds a line for the names, or with `update` replaces the lines that already
+/// have one of them. Returns whether the file changed.
+fn add_names(
+    hosts_file: &mut ManagedHostsFile,
+    ip: &str,
+    all_names: &[&str],
+    comment: &str,
+    update: bool,
+    merge: bool,
+) -> bool {
+    let matches = hosts_file.get_multi_match(all_names, &MatchType::Exact);
     if !matches.is_empty() && !update {
         println!(
             "The requested host is already present: \n{}",
             matches.join("\n")
         );
-        return;
+        return false;
     }
     let names = all_names.join(" ");
-    let comment = comment.join(" ");
-    let computed_comment = if comment.is_empty() {
-        "Added by hostman"
-    } else {
-        &comment
-    };
-    let host_line = format!("{} {} # {}", ip, names, computed_comment);
+    let host_line = format!("{} {} # {}", ip, names, metadata::with_managed_tag(comment));
     if !matches.is_empty() {
         println!(
             "Updating host in hosts file: \n {} \n => {} {} {}",
@@ -88,19 +185,27 @@ pub fn add(args: &Cli, sub_cmd: &CmdAdd) {
             comment
         );
         for host in all_names {
-            println!("Removing host {}", host);
-            hosts_file.remove_host(host);
+            if hosts_file.has_host(host) {
+                println!("Removing host {}", host);
+                hosts_file.remove_host(host);
+            }
         }
     }
 
+    if merge && hosts_file.merge_names(ip, all_names) {
+        println!("Adding {} to the existing {} line", names, ip);
+        return true;
+    }
+
     println!("Adding {} {} to /etc/hosts", ip, names);
     let line = HostsFileLine::from_string(&host_line);
     if let Ok(line) = line {
         println!("{}", format_line(&line));
         hosts_file.add_line(&host_line);
-        maybe_save(args.dry_run, hosts_file);
+        true
     } else {
         println!("Error parsing line: {}", host_line);
+        false
     }
 }
 
@@ -112,12 +217,132 @@ pub fn add_local(args: &Cli, sub_cmd: &CmdAddLocal) {
             names: String::from(sub_cmd.names.as_str()),
             comment: sub_cmd.comment.clone(),
             update: sub_cmd.update,
+            merge: sub_cmd.merge,
+            ttl: sub_cmd.ttl,
+            until: sub_cmd.until,
         },
     )
 }
 
+pub fn alias(args: &Cli, sub_cmd: &CmdAlias) {
+    let mut hosts_file = ManagedHostsFile::must_load_locked();
+    match sub_cmd {
+        CmdAlias::Add { host, names } => {
+            if !hosts_file.has_host(host) {
+                println!("{} is not in hosts file.", host);
+                return;
+            }
+            let names = names.split(',').collect::<Vec<&str>>();
+            if hosts_file.add_aliases(host, &names) == 0 {
+                println!("{} already has these aliases.", host);
+                return;
+            }
+            println!("Adding aliases {} to {}", names.join(" "), host);
+        }
+        CmdAlias::Remove { host, alias } => {
+            if hosts_file.remove_alias(host, alias) == 0 {
+                println!("{} has no alias {} that can be removed.", host, alias);
+                return;
+            }
+            println!("Removing alias {} from {}", alias, host);
+        }
+    }
+    maybe_save(args.dry_run, hosts_file);
+}
+
+pub fn move_host(args: &Cli, host: &str, ip: &str, split: bool) {
+    if hostsfile::address(ip).is_none() {
+        eprintln!("Error: invalid ip address: {}", ip);
+        std::process::exit(1);
+    }
+    let mut hosts_file = ManagedHostsFile::must_load_locked();
+    if !hosts_file.has_host(host) {
+        println!("{} is not in hosts file.", host);
+        return;
+    }
+    if hosts_file.move_host(host, ip, split) == 0 {
+        println!("{} already points to {}.", host, ip);
+        return;
+    }
+    println!("Moving host {} to {}", host, ip);
+    maybe_save(args.dry_run, hosts_file);
+}
+
+pub fn rename(args: &Cli, old: Option<&str>, new: Option<&str>, suffix: Option<&(String, String)>) {
+    let mut hosts_file = ManagedHostsFile::must_load_locked();
+    let changed = match (suffix, old, new) {
+        (Some((from, to)), _, _) => {
+            println!("Renaming hosts ending in {} to end in {}", from, to);
+            hosts_file.rename_hosts(&|h| {
+                h.strip_suffix(from.as_str())
+                    .filter(|n| !n.is_empty())
+                    .map(|n| format!("{}{}", n, to))
+            })
+        }
+        (None, Some(old), Some(new)) => {
+            if hosts_file.has_host(new) {
+                println!("Warning: {} is already in hosts file.", new);
+            }
+            println!("Renaming host {} to {}", old, new);
+            hosts_file.rename_hosts(&|h| {
+                if h == old {
+                    Some(String::from(new))
+                } else {
+                    None
+                }
+            })
+        }
+        _ => 0,
+    };
+    if changed == 0 {
+        println!("No matching hosts in hosts file.");
+        return;
+    }
+    println!("Renamed {} host name(s)", changed);
+    maybe_save(args.dry_run, hosts_file);
+}
+
+pub fn comment(args: &Cli, host: &str, text: &[String]) {
+    if text.is_empty() {
+        let hosts_file = ManagedHostsFile::must_load();
+        let lines = hosts_file.host_lines(host);
+        if lines.is_empty() {
+            println!("{} is not in hosts file.", host);
+        }
+        for line in lines {
+            let comment = line
+                .comment()
+                .map(|c| metadata::text(&c))
+                .unwrap_or_default();
+            println!(
+                "{} {}: {}",
+                line.ip().unwrap().as_str().blue(),
+                line.hosts().join(" ").as_str().green(),
+                comment.cyan()
+            );
+        }
+        return;
+    }
+    let mut hosts_file = ManagedHostsFile::must_load_locked();
+    if !hosts_file.has_host(host) {
+        println!("{} is not in hosts file.", host);
+        return;
+    }
+    let text = text.join(" ");
+    if hosts_file.set_comment(host, &text) == 0 {
+        println!("{} already has this comment.", host);
+        return;
+    }
+    if text.trim().is_empty() {
+        println!("Clearing comment of {}", host);
+    } else {
+        println!("Setting comment of {} to: {}", host, text);
+    }
+    maybe_save(args.dry_run, hosts_file);
+}
+
 pub fn remove(args: &Cli, host: &str) {
-    let mut hosts_file = ManagedHostsFile::must_load();
+    let mut hosts_file = ManagedHostsFile::must_load_locked();
     if !hosts_file.has_host(host) {
         println!("{} not in hosts file.", host);
         return;
@@ -128,7 +353,7 @@ pub fn remove(args: &Cli, host: &str) {
 }
 
 pub fn disable(args: &Cli, host: &str) {
-    let mut hosts_file = ManagedHostsFile::must_load();
+    let mut hosts_file = ManagedHostsFile::must_load_locked();
     if !hosts_file.has_host(host) {
         if hosts_file.has_disabled_host(host) {
             println!("{} is already disabled in hosts file.", host);
@@ -143,7 +368,7 @@ pub fn disable(args: &Cli, host: &str) {
 }
 
 pub fn enable(args: &Cli, host: &str) {
-    let mut hosts_file = ManagedHostsFile::must_load();
+    let mut hosts_file = ManagedHostsFile::must_load_locked();
     if !hosts_file.has_disabled_host(host) {
         if hosts_file.has_host(host) {
             println!("{} is already enabled in hosts file.", host);
@@ -157,12 +382,1247 @@ pub fn enable(args: &Cli, host: &str) {
     maybe_save(args.dry_run, hosts_file);
 }
 
+pub fn toggle(args: &Cli, host: Option<&str>) {
+    let mut hosts_file = ManagedHostsFile::must_load_locked();
+    match host {
+        Some(host) => {
+            if hosts_file.toggle_host(host) == 0 {
+                println!("{} is not in hosts file.", host);
+                return;
+            }
+            println!("Toggling host {}", host);
+        }
+        None => {
+            let entries = hosts_file.entries();
+            if entries.is_empty() {
+                println!("There are no hosts to toggle.");
+                return;
+            }
+            let items = entries
+                .iter()
+                .map(|(i, enabled)| {
+                    let state = if *enabled { "on " } else { "off" };
+                    format!("{} {}", state, hosts_file.line(*i))
+                })
+                .collect::<Vec<String>>();
+            let picked = match picker::multi_select("toggle", &items) {
+                Ok(Some(picked)) if !picked.is_empty() => picked,
+                Ok(_) => {
+                    println!("Nothing toggled.");
+                    return;
+                }
+                Err(err) => {
+                    eprintln!("Error: could not open the host picker: {}", err);
+                    std::process::exit(1);
+                }
+            };
+            for item in picked {
+                let (index, enabled) = entries[item];
+                let action = if enabled { "Disabling" } else { "Enabling" };
+                println!("{} {}", action, hosts_file.line(index));
+                hosts_file.toggle_at(index);
+            }
+        }
+    }
+    maybe_save(args.dry_run, hosts_file);
+}
+
+pub fn prune(args: &Cli, disable: bool) {
+    let mut hosts_file = ManagedHostsFile::must_load_locked();
+    let now = Utc::now();
+    let expired = hosts_file
+        .expired(now)
+        .into_iter()
+        .filter(|l| !disable || l.has_host())
+        .map(format_line)
+        .collect::<Vec<String>>();
+    if expired.is_empty() {
+        println!("No expired hosts.");
+        return;
+    }
+    println!("{}", expired.join("\n"));
+    let count = if disable {
+        hosts_file.disable_expired(now)
+    } else {
+        hosts_file.remove_expired(now)
+    };
+    println!(
+        "{} {} expired line(s)",
+        if disable { "Disabling" } else { "Removing" },
+        count
+    );
+    maybe_save(args.dry_run, hosts_file);
+}
+
+pub fn run(args: &Cli, sub_cmd: &CmdRun) {
+    let CmdRun { add, command } = sub_cmd;
+    let signals = SignalForwarder::install();
+    let hosts_file = ManagedHostsFile::must_load_locked();
+    let code = run_with(hosts_file, add, command, args.dry_run, Some(&signals));
+    std::process::exit(code);
+}
+
+/// Adds the temporary lines, runs the command and removes the lines again once
+/// it exits. Returns the exit code of the command.
+fn run_with(
+    mut hosts_file: ManagedHostsFile,
+    add: &[(String, String)],
+    command: &[String],
+    dry_run: bool,
+    signals: Option<&SignalForwarder>,
+) -> i32 {
+    let run_id = format!(
+        "{}-{}",
+        std::process::id(),
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    );
+    let comment = metadata::with_tag(DEFAULT_COMMENT, RUN_TAG, &run_id);
+
+    for (ip, names) in add {
+        let all_names = names.split(',').collect::<Vec<&str>>();
+        let present = hosts_file.get_multi_match(&all_names, &MatchType::Exact);
+        if !present.is_empty() {
+            println!("Warning: already in hosts file: {}", present.join(" "));
+        }
+        let host_line = format!("{} {} # {}", ip, all_names.join(" "), comment);
+        if HostsFileLine::from_string(&host_line).is_err() {
+            eprintln!("Error parsing line: {}", host_line);
+            std::process::exit(1);
+        }
+        println!("Adding {} {} to /etc/hosts", ip, all_names.join(" "));
+        hosts_file.add_line(&host_line);
+    }
+    let guard = if dry_run {
+        println!("{}", hosts_file);
+        None
+    } else {
+        save_or_exit(&hosts_file, Action::Change);
+        Some(RunGuard {
+            file_name: String::from(hosts_file.file_name()),
+            run_id,
+        })
+    };
+    drop(hosts_file);
+
+    let code = match Command::new(&command[0]).args(&command[1..]).spawn() {
+        Ok(mut child) => {
+            if let Some(signals) = signals {
+                signals.forward_to(child.id());
+            }
+            match child.wait() {
+                Ok(status) => exit_code(status),
+                Err(e) => {
+                    eprintln!("Error waiting for {}: {}", command[0], e);
+                    1
+                }
+            }
+        }
+        Err(e) => {
+            eprintln!("Error: cannot run {}: {}", command[0], e);
+            127
+        }
+    };
+    drop(guard);
+    code
+}
+
+/// Removes the lines added by a `run` invocation when dropped, including on panic.
+struct RunGuard {
+    file_name: String,
+    run_id: String,
+}
+
+impl Drop for RunGuard {
+    fn drop(&mut self) {
+        let mut hosts_file = ManagedHostsFile::must_load_file_locked(&self.file_name);
+        let removed = hosts_file.remove_tagged(RUN_TAG, &self.run_id);
+        if removed == 0 {
+            return;
+        }
+        println!("Removing {} temporary line(s) from /etc/hosts", removed);
+        match hosts_file.save() {
+            Ok(()) => record_change(&hosts_file, Action::Change),
+            Err(ConflictError { file_name }) => eprintln!(
+                "Error: {} was changed by another program; the temporary lines tagged [{}={}] were not removed.",
+                file_name, RUN_TAG, self.run_id
+            ),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn exit_code(status: ExitStatus) -> i32 {
+    use std::os::unix::process::ExitStatusExt;
+    status
+        .code()
+        .unwrap_or_else(|| 128 + status.signal().unwrap_or(0))
+}
+
+#[cfg(not(unix))]
+fn exit_code(status: ExitStatus) -> i32 {
+    status.code().unwrap_or(1)
+}
+
+/// Catches termination signals so `run` can clean up, and relays them to the
+/// child. A SIGINT or SIGQUIT typed in the terminal already reaches the child
+/// through its process group, so it is only relayed when another process sent
+/// it or the child is not in the foreground.
+#[cfg(unix)]
+struct SignalForwarder {
+    child: std::sync::Arc<std::sync::atomic::AtomicI32>,
+}
+
+#[cfg(unix)]
+impl SignalForwarder {
+    fn install() -> SignalForwarder {
+        use signal_hook::consts::{SIGHUP, SIGINT, SIGQUIT, SIGTERM};
+        use signal_hook::iterator::exfiltrator::WithOrigin;
+        use signal_hook::low_level::siginfo::Cause;
+        use std::sync::atomic::Ordering;
+        let child = std::sync::Arc::new(std::sync::atomic::AtomicI32::new(0));
+        let mut signals = signal_hook::iterator::SignalsInfo::<WithOrigin>::new([
+            SIGHUP, SIGINT, SIGQUIT, SIGTERM,
+        ])
+        .expect("cannot install signal handlers");
+        let target = child.clone();
+        std::thread::spawn(move || {
+            for origin in signals.forever() {
+                let pid = target.load(Ordering::SeqCst);
+                let from_terminal = (origin.signal == SIGINT || origin.signal == SIGQUIT)
+                    && !matches!(origin.cause, Cause::Sent(_))
+                    && in_foreground(pid);
+                if pid > 0 && !from_terminal {
+                    unsafe {
+                        libc::kill(pid, origin.signal);
+                    }
+                }
+            }
+        });
+        SignalForwarder { child }
+    }
+
+    fn forward_to(&self, pid: u32) {
+        self.child
+            .store(pid as i32, std::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+/// Whether the process is in the foreground process group of the terminal.
+#[cfg(unix)]
+fn in_foreground(pid: i32) -> bool {
+    unsafe {
+        let group = libc::tcgetpgrp(libc::STDIN_FILENO);
+        group > 0 && group == libc::getpgid(pid)
+    }
+}
+
+#[cfg(not(unix))]
+struct SignalForwarder;
+
+#[cfg(not(unix))]
+impl SignalForwarder {
+    fn install() -> SignalForwarder {
+        SignalForwarder
+    }
+
+    fn forward_to(&self, _pid: u32) {}
+}
+
+pub fn undo(args: &Cli) {
+    let hosts_file = ManagedHostsFile::must_load_locked();
+    let records = journal::load(hosts_file.file_name());
+    let (applied, _) = journal::stacks(&records);
+    match applied.last() {
+        Some(record) => {
+            println!("Undoing {}", describe_record(record));
+            let action = Action::Undo { of: record.id };
+            replay(
+                args,
+                hosts_file,
+                record,
+                &diff::invert(&record.hunks),
+                action,
+            );
+        }
+        None => println!("Nothing to undo."),
+    }
+}
+
+pub fn redo(args: &Cli) {
+    let hosts_file = ManagedHostsFile::must_load_locked();
+    let records = journal::load(hosts_file.file_name());
+    let (_, undone) = journal::stacks(&records);
+    match undone.last() {
+        Some(record) => {
+            println!("Redoing {}", describe_record(record));
+            let action = Action::Redo { of: record.id };
+            replay(args, hosts_file, record, &record.hunks, action);
+        }
+        None => println!("Nothing to redo."),
+    }
+}
+
+fn replay(
+    args: &Cli,
+    mut hosts_file: ManagedHostsFile,
+    record: &Record,
+    hunks: &[diff::Hunk],
+    action: Action,
+) {
+    let current = hosts_file.loaded().lines().collect::<Vec<&str>>();
+    match diff::apply(&current, hunks) {
+        Ok(lines) => {
+            hosts_file.set_contents(&lines.join("\n"));
+            if args.dry_run {
+                println!("{}", hosts_file);
+            } else {
+                save_or_exit(&hosts_file, action);
+            }
+        }
+        Err(diff::DivergedError { line }) => {
+            eprintln!(
+                "Error: line {} of {} changed after #{}; refusing to replay it.",
+                line + 1,
+                hosts_file.file_name(),
+                record.id
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Which entries to export.
+pub struct Selection<'a> {
+    /// Text the ip, names or comment must contain.
+    pub filter: Option<&'a str>,
+    /// Host the line must match, as in `check`.
+    pub host: Option<&'a str>,
+    pub exact: bool,
+    /// Whether to keep `localhost` and the other names of the system.
+    pub all: bool,
+}
+
+/// The zone file settings given to `export`.
+pub struct ZoneOptions<'a> {
+    pub origin: Option<&'a str>,
+    pub nameserver: Option<&'a str>,
+    pub ptr: bool,
+}
+
+pub fn export(
+    selection: &Selection,
+    format: Format,
+    wildcard: bool,
+    output: Option<&str>,
+    zone: &ZoneOptions,
+) {
+    if let (Format::Zone, Some(origin)) = (format, zone.origin) {
+        export_zone(selection, output, origin, zone.nameserver, zone.ptr);
+        return;
+    }
+    if zone.origin.is_some() || zone.nameserver.is_some() || zone.ptr {
+        eprintln!("Error: --origin, --nameserver and --ptr only apply to --format zone");
+        std::process::exit(1);
+    }
+    let entries = exported_entries(selection);
+    let text = entry::serialize(&entries, format, wildcard).unwrap_or_else(|err| {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    });
+    match output {
+        None => print!("{}", text),
+        Some(file) => {
+            write_or_exit(file, &text);
+            println!("Exported {} entries to {}", entries.len(), file);
+        }
+    }
+}
+
+/// Exports a zone for `origin`. Reverse zones are printed after it, or written
+/// next to `output` as `db.<zone>`.
+fn export_zone(
+    selection: &Selection,
+    output: Option<&str>,
+    origin: &str,
+    nameserver: Option<&str>,
+    ptr: bool,
+) {
+    let entries = exported_entries(selection);
+    let serial = Utc::now().timestamp() as u32;
+    let (zones, warnings) = zone::zones(&entries, origin, nameserver, ptr, serial);
+    for warning in warnings {
+        eprintln!("{}", format!("Warning: {}", warning).yellow());
+    }
+    let file = match output {
+        Some(file) => std::path::Path::new(file),
+        None => {
+            let texts = zones.iter().map(|z| z.text.as_str()).collect::<Vec<&str>>();
+            print!("{}", texts.join("\n"));
+            return;
+        }
+    };
+    for (i, zone) in zones.iter().enumerate() {
+        let path = if i == 0 {
+            file.to_path_buf()
+        } else {
+            file.with_file_name(format!("db.{}", zone.name))
+        };
+        let path = path.to_string_lossy();
+        write_or_exit(&path, &zone.text);
+        println!("Exported zone {} to {}", zone.name, path);
+    }
+}
+
+fn exported_entries(selection: &Selection) -> Vec<Entry> {
+    let hosts_file = ManagedHostsFile::must_load();
+    let entries = match selection.host {
+        Some(host) => entry::matching(&hosts_file, host, &MatchType::from_bool(selection.exact)),
+        None => entry::entries(&hosts_file),
+    };
+    entries
+        .into_iter()
+        .filter(|e| selection.filter.map(|f| e.matches(f)).unwrap_or(true))
+        .filter_map(|e| {
+            if selection.all {
+                Some(e)
+            } else {
+                e.without_system_names()
+            }
+        })
+        .collect()
+}
+
+fn write_or_exit(file: &str, contents: &str) {
+    if let Err(err) = std::fs::write(file, contents) {
+        eprintln!("Error: could not write {}: {}", file, err);
+        std::process::exit(1);
+    }
+}
+
+pub fn import(args: &Cli, file: &str, format: Option<Format>, on_conflict: Conflict) {
+    let format = format
+        .or_else(|| Format::from_path(file))
+        .unwrap_or_else(|| {
+            eprintln!(
+                "Error: cannot tell the format of {}, please pass --format",
+                file
+            );
+            std::process::exit(1);
+        });
+    let contents = read_input(Some(file));
+    let entries = entry::deserialize(&contents, format).unwrap_or_else(|err| {
+        eprintln!("Error: could not parse {}: {}", file, err);
+        std::process::exit(1);
+    });
+    import_entries(
+        args,
+        ManagedHostsFile::must_load_locked(),
+        &entries,
+        on_conflict,
+    );
+}
+
+/// Dispatches `import compose`, `import k8s` and the web server importers.
+/// The web server importers always update existing names, so they refuse an
+/// explicit `--on-conflict`.
+pub fn import_source(args: &Cli, source: &CmdImport, on_conflict: Option<Conflict>) {
+    let vhosts = matches!(
+        source,
+        CmdImport::Nginx { .. } | CmdImport::Apache { .. } | CmdImport::Caddy { .. }
+    );
+    if vhosts && on_conflict.is_some() {
+        eprintln!("Error: --on-conflict does not apply to web server configs, their names always replace existing ones");
+        std::process::exit(1);
+    }
+    let on_conflict = on_conflict.unwrap_or_default();
+    match source {
+        CmdImport::Compose { file, ip } => import_names(
+            args,
+            std::slice::from_ref(file),
+            ip,
+            on_conflict,
+            manifests::compose_hosts,
+        ),
+        CmdImport::K8s { manifests, ip } => {
+            import_names(args, manifests, ip, on_conflict, manifests::ingress_hosts)
+        }
+        CmdImport::Leases { files, domain } => {
+            import_leases(args, files, domain.as_deref(), on_conflict)
+        }
+        CmdImport::Nginx { files, ip } => import_vhosts(args, files, ip, vhosts::nginx),
+        CmdImport::Apache { files, ip } => import_vhosts(args, files, ip, vhosts::apache),
+        CmdImport::Caddy { files, ip } => import_vhosts(args, files, ip, vhosts::caddy),
+        CmdImport::Extra(_) => unreachable!("the file argument takes the first value"),
+    }
+}
+
+/// Adds the host names declared in compose files or kubernetes manifests,
+/// recording the file they came from in the comment.
+fn import_names(
+    args: &Cli,
+    files: &[String],
+    ip: &str,
+    on_conflict: Conflict,
+    extract: fn(&str) -> Result<Vec<String>, String>,
+) {
+    let mut entries: Vec<Entry> = vec![];
+    for file in files {
+        let hosts = extract(&read_input(Some(file))).unwrap_or_else(|err| {
+            eprintln!("Error: could not parse {}: {}", file, err);
+            std::process::exit(1);
+        });
+        let comment = source_comment(file);
+        for host in hosts {
+            if entries.iter().all(|e| e.names[0] != host) {
+                entries.push(Entry {
+                    ip: String::from(ip),
+                    names: vec![host],
+                    comment: Some(comment.clone()),
+                    enabled: true,
+                });
+            }
+        }
+    }
+    if entries.is_empty() {
+        eprintln!("No host names found.");
+        return;
+    }
+    import_entries(
+        args,
+        ManagedHostsFile::must_load_locked(),
+        &entries,
+        on_conflict,
+    );
+}
+
+/// Adds the server names of web server configs, replacing the lines that
+/// already have one of them like `add --update` does.
+fn import_vhosts(args: &Cli, files: &[String], ip: &str, parse: fn(&str) -> Vhosts) {
+    let mut hosts_file = ManagedHostsFile::must_load_locked();
+    for file in files {
+        let vhosts = parse(&read_input(Some(file)));
+        for warning in vhosts.warnings {
+            eprintln!("{}", format!("Warning: {}: {}", file, warning).yellow());
+        }
+        let comment = source_comment(file);
+        for server in vhosts.servers {
+            let present = hosts_file.entries().into_iter().any(|(i, enabled)| {
+                let line = hosts_file.line(i);
+                enabled && line.ip().as_deref() == Some(ip) && line.hosts() == server
+            });
+            if present {
+                println!("{} {} is already in hosts file", ip, server.join(" "));
+                continue;
+            }
+            let names = server.iter().map(String::as_str).collect::<Vec<&str>>();
+            add_names(&mut hosts_file, ip, &names, &comment, true, false);
+        }
+    }
+    if hosts_file.is_unchanged() {
+        return;
+    }
+    maybe_save(args.dry_run, hosts_file);
+}
+
+/// The default comment with the canonical path of the file an entry came from.
+fn source_comment(file: &str) -> String {
+    let path = std::fs::canonicalize(file)
+        .map(|p| p.display().to_string())
+        .unwrap_or_else(|_| String::from(file));
+    metadata::with_tag(DEFAULT_COMMENT, metadata::SOURCE_TAG, &path)
+}
+
+/// Adds the host names of DHCP leases. Lines imported from the same file
+/// earlier are replaced when the lease moved to another address.
+fn import_leases(args: &Cli, files: &[String], domain: Option<&str>, on_conflict: Conflict) {
+    let mut entries: Vec<Entry> = vec![];
+    for file in files {
+        let leases = leases::parse(&read_input(Some(file)), Utc::now()).unwrap_or_else(|err| {
+            eprintln!("Error: could not parse {}: {}", file, err);
+            std::process::exit(1);
+        });
+        let comment = source_comment(file);
+        entries.extend(leases.into_iter().map(|lease| Entry {
+            ip: lease.ip,
+            names: vec![match domain {
+                Some(domain) => format!("{}.{}", lease.hostname, domain.trim_matches('.')),
+                None => lease.hostname,
+            }],
+            comment: Some(comment.clone()),
+            enabled: true,
+        }));
+    }
+    if entries.is_empty() {
+        eprintln!("No leases with a host name found.");
+        return;
+    }
+    let mut hosts_file = ManagedHostsFile::must_load_locked();
+    remove_stale_leases(&mut hosts_file, &entries);
+    import_entries(args, hosts_file, &entries, on_conflict);
+}
+
+/// Removes the lines an earlier import of the same file gave an address the file
+/// no longer has for that name, such as an IPv6 lease that has since expired.
+fn remove_stale_leases(hosts_file: &mut ManagedHostsFile, entries: &[Entry]) {
+    let mut stale = vec![];
+    for entry in entries {
+        let source = entry
+            .comment
+            .as_deref()
+            .and_then(|c| metadata::get_tag(c, metadata::SOURCE_TAG));
+        for index in hosts_file.find_entries(&entry.names[0]) {
+            let line = hosts_file.line(index);
+            let ip = line.ip().unwrap_or_default();
+            if line
+                .comment()
+                .and_then(|c| metadata::get_tag(&c, metadata::SOURCE_TAG))
+                == source
+                && !entries.iter().any(|e| e.names == entry.names && e.ip == ip)
+            {
+                println!("Replacing stale address {} of {}", ip, entry.names[0]);
+                stale.push(index);
+            }
+        }
+    }
+    stale.sort_unstable();
+    stale.dedup();
+    for index in stale.into_iter().rev() {
+        hosts_file.remove_at(index);
+    }
+}
+
+fn import_entries(
+    args: &Cli,
+    mut hosts_file: ManagedHostsFile,
+    entries: &[Entry],
+    on_conflict: Conflict,
+) {
+    let summary = entry::import(&mut hosts_file, entries, on_conflict).unwrap_or_else(|err| {
+        eprintln!("{}", format!("Error: {}", err).red());
+        eprintln!("No changes were made.");
+        std::process::exit(1);
+    });
+    println!(
+        "Imported {} entries: {} added, {} replaced, {} skipped.",
+        entries.len(),
+        summary.added,
+        summary.replaced,
+        summary.skipped
+    );
+    if hosts_file.is_unchanged() {
+        return;
+    }
+    maybe_save(args.dry_run, hosts_file);
+}
+
+pub fn blocklist(args: &Cli, sub_cmd: &CmdBlocklist) {
+    if let CmdBlocklist::List {} = sub_cmd {
+        list_blocklists();
+        return;
+    }
+    let mut hosts_file = ManagedHostsFile::must_load_locked();
+    let file_name = String::from(hosts_file.file_name());
+    let mut allowlist = blocklist::load_allowlist(&file_name);
+    let lists = blocklist::lists(&hosts_file);
+    let fetch = |name: &str, source: &str| match blocklist::fetch(source) {
+        Ok(contents) => Some((String::from(name), blocklist::parse(&contents))),
+        Err(err) => {
+            eprintln!(
+                "{}",
+                format!("Warning: cannot fetch {}: {}", name, err).yellow()
+            );
+            None
+        }
+    };
+    let fetched = match sub_cmd {
+        CmdBlocklist::List {} => unreachable!(),
+        CmdBlocklist::Add { name, source } => {
+            if lists.iter().any(|l| l.name == *name) {
+                println!(
+                    "{} is already subscribed, use `blocklist update {}`.",
+                    name, name
+                );
+                return;
+            }
+            let list = fetch(name, source).unwrap_or_else(|| std::process::exit(1));
+            blocklist::put(
+                &mut hosts_file,
+                &blocklist::Blocklist {
+                    name: name.clone(),
+                    source: source.clone(),
+                    domains: vec![],
+                },
+            );
+            vec![list]
+        }
+        CmdBlocklist::Update { name } => {
+            let selected = lists
+                .iter()
+                .filter(|l| name.as_ref().map_or(true, |n| *n == l.name))
+                .collect::<Vec<&blocklist::Blocklist>>();
+            if selected.is_empty() {
+                println!("No blocklist to update.");
+                return;
+            }
+            selected
+                .iter()
+                .filter_map(|l| fetch(&l.name, &l.source))
+                .collect()
+        }
+        CmdBlocklist::Remove { name } => {
+            if !blocklist::remove(&mut hosts_file, name) {
+                println!("{} is not subscribed.", name);
+                return;
+            }
+            println!("Removing blocklist {}", name);
+            vec![]
+        }
+        CmdBlocklist::Allow { remove, names } => {
+            let names = names.iter().map(|n| n.to_lowercase());
+            if *remove {
+                let names = names.collect::<Vec<String>>();
+                allowlist.retain(|a| !names.contains(a));
+            } else {
+                allowlist.extend(names.filter(|n| !allowlist.contains(n)).collect::<Vec<_>>());
+            }
+            if args.dry_run {
+                println!("Allowlist: {}", allowlist.join(" "));
+            } else if let Err(e) = blocklist::save_allowlist(&file_name, &allowlist) {
+                eprintln!(
+                    "Error: cannot write {}: {}",
+                    blocklist::allowlist_path(&file_name),
+                    e
+                );
+                std::process::exit(1);
+            }
+            if *remove {
+                println!("Run `hostman blocklist update` to block them again.");
+            }
+            vec![]
+        }
+    };
+    for (name, count) in blocklist::rebuild(&mut hosts_file, &fetched, &allowlist) {
+        println!("{}: {} domains blocked", name, count);
+    }
+    if hosts_file.is_unchanged() {
+        return;
+    }
+    maybe_save(args.dry_run, hosts_file);
+}
+
+fn list_blocklists() {
+    let hosts_file = ManagedHostsFile::must_load();
+    for list in blocklist::lists(&hosts_file) {
+        println!(
+            "{} ({} domains): {}",
+            list.name,
+            list.domains.len(),
+            list.source
+        );
+    }
+    let allowlist = blocklist::load_allowlist(hosts_file.file_name());
+    if !allowlist.is_empty() {
+        println!("Allowed: {}", allowlist.join(" "));
+    }
+}
+
+pub fn block(args: &Cli, sub_cmd: &CmdBlock) {
+    let CmdBlock {
+        duration,
+        until,
+        weekdays,
+        weekends,
+        daily,
+        domains,
+    } = sub_cmd;
+    let expires = expiry_or_exit(*duration, *until);
+    let schedule = match (weekdays, weekends, daily) {
+        (Some(range), _, _) => Some((Days::Weekdays, range)),
+        (_, Some(range), _) => Some((Days::Weekends, range)),
+        (_, _, Some(range)) => Some((Days::Daily, range)),
+        _ => None,
+    }
+    .map(|(days, (start, end))| Schedule {
+        days,
+        start: *start,
+        end: *end,
+    });
+    let mut hosts_file = ManagedHostsFile::must_load_locked();
+    let now = Local::now().naive_local();
+    for domain in domains {
+        match block::add(&mut hosts_file, domain, expires, schedule, now) {
+            Ok(names) => {
+                let mut message = format!("Blocking {}", names.join(" "));
+                if let Some(s) = schedule {
+                    message.push_str(&format!(" {}", s));
+                }
+                if let Some(t) = expires {
+                    message.push_str(&format!(
+                        " until {}",
+                        t.with_timezone(&Local).format("%Y-%m-%d %H:%M")
+                    ));
+                }
+                println!("{}", message);
+            }
+            Err(err) => println!("Warning: not blocking {}: {}", domain, err),
+        }
+    }
+    if hosts_file.is_unchanged() {
+        return;
+    }
+    if schedule.is_some() || expires.is_some() {
+        println!("Blocks are lifted and scheduled while `hostman daemon` runs.");
+    }
+    maybe_save(args.dry_run, hosts_file);
+}
+
+pub fn unblock(args: &Cli, domains: &[String]) {
+    let mut hosts_file = ManagedHostsFile::must_load_locked();
+    for domain in domains {
+        if block::remove(&mut hosts_file, domain) == 0 {
+            println!("{} is not blocked.", domain);
+        } else {
+            println!("Unblocking {}", domain);
+        }
+    }
+    if hosts_file.is_unchanged() {
+        return;
+    }
+    maybe_save(args.dry_run, hosts_file);
+}
+
+/// Removes expired lines, applies block schedules and restores the lines
+/// hostman added, and those of the `enforce` file, when other programs remove
+/// or change them. The hosts file is checked whenever it changes, where it can
+/// be watched, and every `interval`.
+pub fn daemon(
+    args: &Cli,
+    interval: std::time::Duration,
+    debounce: std::time::Duration,
+    enforce: Option<&str>,
+) {
+    let hosts_file = ManagedHostsFile::must_load();
+    let file_name = String::from(hosts_file.file_name());
+    let mut files = vec![file_name.as_str()];
+    files.extend(enforce);
+    let mut watcher = match daemon::Watcher::new(&files) {
+        Ok(watcher) => {
+            log(&format!(
+                "Watching {}, checking every {}",
+                files.join(" and "),
+                humantime::format_duration(interval)
+            ));
+            Some(watcher)
+        }
+        Err(err) => {
+            log(&format!(
+                "Cannot watch {} ({}), checking it every {}",
+                file_name,
+                err,
+                humantime::format_duration(interval)
+            ));
+            None
+        }
+    };
+    let mut status = daemon::Status {
+        pid: std::process::id(),
+        started: Utc::now(),
+        restored: 0,
+        last_restore: None,
+        enforce: enforce.map(String::from),
+        desired: drift::managed(&hosts_file),
+        sections: drift::managed_sections(&hosts_file),
+    };
+    let mut last_record = last_record_id(&file_name);
+    let mut saved = None;
+    drop(hosts_file);
+    loop {
+        daemon_tick(&file_name, args.dry_run, &mut status, &mut last_record);
+        if saved.as_ref() != Some(&status) {
+            match daemon::save_status(&file_name, &status) {
+                Ok(()) => saved = Some(status.clone()),
+                Err(err) => log(&format!(
+                    "Warning: cannot write {}: {}",
+                    daemon::status_path(&file_name),
+                    err
+                )),
+            }
+        }
+        match watcher.as_mut() {
+            Some(watcher) => {
+                watcher.wait(interval, debounce);
+            }
+            None => std::thread::sleep(interval),
+        }
+    }
+}
+
+fn daemon_tick(
+    file_name: &str,
+    dry_run: bool,
+    status: &mut daemon::Status,
+    last_record: &mut Option<u64>,
+) {
+    let mut hosts_file = ManagedHostsFile::must_load_file_locked(file_name);
+    // Changes made with hostman since the last check are kept, not reverted.
+    let record = last_record_id(file_name);
+    if record != *last_record {
+        status.desired = drift::managed(&hosts_file);
+        status.sections = drift::managed_sections(&hosts_file);
+        *last_record = record;
+    }
+    let mut desired = status.desired.clone();
+    if let Some(file) = &status.enforce {
+        match enforced_lines(file) {
+            Ok(lines) => desired.extend(lines),
+            Err(err) => log(&format!("Warning: cannot read {}: {}", file, err)),
+        }
+    }
+    let sections = drift::restore_sections(&mut hosts_file, &status.sections)
+        .into_iter()
+        .map(|s| (s.name.clone(), s.lines.len()))
+        .collect::<Vec<(String, usize)>>();
+    let restored = drift::restore(&mut hosts_file, &desired);
+    let expired = hosts_file.remove_expired(Utc::now());
+    let scheduled = block::apply_schedules(&mut hosts_file, Local::now().naive_local());
+    if hosts_file.is_unchanged() {
+        return;
+    }
+    for (name, lines) in &sections {
+        log(&format!("Restoring section {} ({} line(s))", name, lines));
+    }
+    for line in &restored {
+        log(&format!("Restoring {}", line));
+    }
+    if expired > 0 || scheduled > 0 {
+        log(&format!(
+            "Removing {} expired line(s), switching {} scheduled line(s)",
+            expired, scheduled
+        ));
+    }
+    if dry_run {
+        println!("{}", hosts_file);
+    } else if hosts_file.save().is_err() {
+        log("The hosts file was changed by another program, trying again later");
+        return;
+    } else {
+        record_change(&hosts_file, Action::Change);
+        *last_record = last_record_id(file_name);
+    }
+    if !restored.is_empty() || !sections.is_empty() {
+        status.restored += restored.len() + sections.iter().map(|(_, n)| n).sum::<usize>();
+        status.last_restore = Some(Utc::now());
+    }
+    status.desired = drift::managed(&hosts_file);
+    status.sections = drift::managed_sections(&hosts_file);
+}
+
+fn last_record_id(file_name: &str) -> Option<u64> {
+    journal::load(file_name).last().map(|r| r.id)
+}
+
+/// Returns the entries of an enforcement file. A missing file enforces nothing,
+/// so it can be created while the daemon runs.
+fn enforced_lines(file: &str) -> Result<Vec<String>, String> {
+    let contents = match std::fs::read_to_string(file) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.to_string()),
+    };
+    drift::parse_enforced(&contents)
+}
+
+pub fn daemon_status() {
+    let hosts_file = ManagedHostsFile::must_load();
+    let status = match daemon::load_status(hosts_file.file_name()) {
+        Some(status) if daemon::is_running(status.pid) => status,
+        _ => {
+            println!("hostman daemon is {}.", "not running".red());
+            std::process::exit(1);
+        }
+    };
+    let time = |t: chrono::DateTime<Utc>| t.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S");
+    println!(
+        "hostman daemon is {} (pid {}) since {}",
+        "running".green(),
+        status.pid,
+        time(status.started)
+    );
+    match status.last_restore {
+        Some(t) => println!("Restored {} line(s), last at {}", status.restored, time(t)),
+        None => println!("Restored no lines"),
+    }
+    let mut desired = status.desired;
+    if let Some(file) = &status.enforce {
+        match enforced_lines(file) {
+            Ok(lines) => desired.extend(lines),
+            Err(err) => println!("Warning: cannot read {}: {}", file, err),
+        }
+    }
+    let changed = drift::changed_sections(&hosts_file, &status.sections);
+    if changed.is_empty() {
+        println!("Keeping {} section(s), all present", status.sections.len());
+    } else {
+        println!(
+            "Keeping {} section(s), {} missing or changed:",
+            status.sections.len(),
+            changed.len()
+        );
+        for section in changed {
+            println!("  {}", section.name.yellow());
+        }
+    }
+    let missing = drift::missing(&hosts_file, &desired);
+    if missing.is_empty() {
+        println!("Keeping {} line(s), all present", desired.len());
+    } else {
+        println!(
+            "Keeping {} line(s), {} missing:",
+            desired.len(),
+            missing.len()
+        );
+        for line in missing {
+            println!("  {}", line.yellow());
+        }
+    }
+}
+
+fn log(message: &str) {
+    println!("[{}] {}", Local::now().format("%Y-%m-%d %H:%M:%S"), message);
+}
+
+pub fn batch(args: &Cli, file: Option<&str>) {
+    let contents = read_input(file);
+    let operations = batch::parse(&contents).unwrap_or_else(|err| {
+        eprintln!("{}", format!("Error: {}", err).red());
+        eprintln!("No changes were made.");
+        std::process::exit(1);
+    });
+    let mut hosts_file = ManagedHostsFile::must_load_locked();
+    for (line, operation) in &operations {
+        match batch::apply(&mut hosts_file, operation) {
+            Ok(description) => println!("{}", description),
+            Err(message) => {
+                eprintln!("{}", format!("Error: line {}: {}", line, message).red());
+                eprintln!("No changes were made.");
+                std::process::exit(1);
+            }
+        }
+    }
+    if hosts_file.is_unchanged() {
+        println!("No changes.");
+        return;
+    }
+    maybe_save(args.dry_run, hosts_file);
+}
+
+pub fn tui(args: &Cli) {
+    let hosts_file = ManagedHostsFile::must_load_locked();
+    let hosts_file = match tui::run(hosts_file) {
+        Ok(Some(hosts_file)) => hosts_file,
+        Ok(None) => {
+            println!("No changes were made.");
+            return;
+        }
+        Err(err) => {
+            eprintln!("Error: could not start the terminal UI: {}", err);
+            std::process::exit(1);
+        }
+    };
+    print_diff(hosts_file.original(), &hosts_file.contents());
+    for error in hosts_file.validate() {
+        eprintln!("{}", format!("Warning: {}", error).yellow());
+    }
+    maybe_save(args.dry_run, hosts_file);
+}
+
+pub fn edit(args: &Cli) {
+    let mut hosts_file = ManagedHostsFile::must_load_locked();
+    let mut temp = tempfile::Builder::new()
+        .prefix("hosts.")
+        .tempfile()
+        .expect("cannot create temporary file");
+    temp.write_all(hosts_file.original().as_bytes())
+        .expect("cannot write temporary file");
+
+    let edited = loop {
+        if !launch_editor(temp.path()) {
+            std::process::exit(1);
+        }
+        let contents = std::fs::read_to_string(temp.path()).expect("cannot read temporary file");
+        let errors = match ManagedHostsFile::try_from_string(&contents, hosts_file.file_name()) {
+            Ok(parsed) => parsed.validate(),
+            Err(e) => vec![e],
+        };
+        if errors.is_empty() {
+            break contents;
+        }
+        for error in errors {
+            eprintln!("{}", format!("Error: {}", error).red());
+        }
+        if !confirm("Edit again?", true) {
+            println!("No changes were made.");
+            return;
+        }
+    };
+
+    let before = hosts_file.contents();
+    hosts_file.set_contents(&edited);
+    let after = hosts_file.contents();
+    if before == after {
+        println!("No changes.");
+        return;
+    }
+    print_diff(&before, &after);
+    if !confirm("Install these changes?", false) {
+        println!("No changes were made.");
+        return;
+    }
+    maybe_save(args.dry_run, hosts_file);
+}
+
+pub fn dedupe(args: &Cli, max_aliases: Option<usize>) {
+    let mut hosts_file = ManagedHostsFile::must_load_locked();
+    let before = hosts_file.contents();
+    hosts_file.dedupe(max_aliases);
+    let after = hosts_file.contents();
+    if before == after {
+        println!("No duplicates found.");
+        return;
+    }
+    print_diff(&before, &after);
+    if !args.dry_run {
+        save_or_exit(&hosts_file, Action::Change);
+    }
+}
+
+pub fn fmt(args: &Cli, sort: Option<SortOrder>, check: bool) {
+    let mut hosts_file = if check {
+        ManagedHostsFile::must_load()
+    } else {
+        ManagedHostsFile::must_load_locked()
+    };
+    hosts_file.format(&FormatOptions { sort });
+    if hosts_file.original() == hosts_file.contents() {
+        println!("{} is formatted.", hosts_file.file_name());
+        return;
+    }
+    if check {
+        print_diff(hosts_file.original(), &hosts_file.contents());
+        std::process::exit(1);
+    }
+    maybe_save(args.dry_run, hosts_file);
+}
+
+fn launch_editor(path: &std::path::Path) -> bool {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| String::from("vi"));
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().unwrap_or("vi");
+    match Command::new(program).args(parts).arg(path).status() {
+        Ok(status) if status.success() => true,
+        Ok(_) => {
+            eprintln!("Error: {} exited with an error", editor);
+            false
+        }
+        Err(e) => {
+            eprintln!("Error: cannot run editor {}: {}", editor, e);
+            false
+        }
+    }
+}
+
+fn confirm(question: &str, default: bool) -> bool {
+    print!("{} [{}] ", question, if default { "Y/n" } else { "y/N" });
+    std::io::stdout().flush().unwrap();
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+    match answer.trim().to_lowercase().as_str() {
+        "" => default,
+        "y" | "yes" => true,
+        _ => false,
+    }
+}
+
+fn print_diff(old: &str, new: &str) {
+    let old = old.lines().collect::<Vec<&str>>();
+    let new = new.lines().collect::<Vec<&str>>();
+    for hunk in diff::diff_lines(&old, &new) {
+        println!("{}", format!("@@ line {} @@", hunk.start + 1).cyan());
+        print_hunk(&hunk);
+    }
+}
+
+fn print_hunk(hunk: &diff::Hunk) {
+    for line in &hunk.removed {
+        println!("{}", format!("- {}", line).red());
+    }
+    for line in &hunk.added {
+        println!("{}", format!("+ {}", line).green());
+    }
+}
+
+pub fn history(host: Option<&str>) {
+    let hosts_file = ManagedHostsFile::must_load();
+    let records = journal::load(hosts_file.file_name());
+    let matching = records
+        .iter()
+        .filter(|r| host.map(|h| journal::touches(r, h)).unwrap_or(true))
+        .collect::<Vec<&Record>>();
+    if matching.is_empty() {
+        println!("No recorded changes.");
+        return;
+    }
+    for record in matching {
+        println!("{}", describe_record(record).yellow());
+        for hunk in &record.hunks {
+            print_hunk(hunk);
+        }
+    }
+}
+
+pub fn blame() {
+    let hosts_file = ManagedHostsFile::must_load();
+    let records = journal::load(hosts_file.file_name());
+    for line in hosts_file.without_comments() {
+        let annotation = match journal::last_added(&records, &format!("{}", line)) {
+            Some(r) => format!(
+                "#{:<4} {} {:<10}",
+                r.id,
+                r.timestamp.with_timezone(&chrono::Local).format("%Y-%m-%d"),
+                r.user
+            ),
+            None => format!("{:<27}", "-"),
+        };
+        println!("{} {}", annotation.dimmed(), format_line(line));
+    }
+}
+
+fn describe_record(record: &Record) -> String {
+    format!(
+        "#{} `{}` by {} at {}",
+        record.id,
+        record.command,
+        record.user,
+        record
+            .timestamp
+            .with_timezone(&chrono::Local)
+            .format("%Y-%m-%d %H:%M:%S")
+    )
+}
+
 pub fn update() {
     let target = self_update::get_target();
     let status = self_update::backends::github::Update::configure()
         .repo_owner("lucascaro")
         .repo_name("hostman")
-        .target(&target)
+        .target(target)
         .bin_name("hostman")
         .show_download_progress(true)
         .current_version(cargo_crate_version!())
@@ -173,10 +1633,167 @@ pub fn update() {
     println!("Update status: `{}`!", status.version());
 }
 
+/// Reads a file, or stdin when no file or `-` is given, exiting on errors.
+fn read_input(file: Option<&str>) -> String {
+    let contents = match file {
+        None | Some("-") => {
+            let mut contents = String::new();
+            std::io::Read::read_to_string(&mut std::io::stdin(), &mut contents).map(|_| contents)
+        }
+        Some(file) => std::fs::read_to_string(file),
+    };
+    contents.unwrap_or_else(|err| {
+        eprintln!("Error: could not read {}: {}", file.unwrap_or("stdin"), err);
+        std::process::exit(1);
+    })
+}
+
 fn maybe_save(dry_run: bool, hosts_file: ManagedHostsFile) {
     if dry_run {
         println!("{}", hosts_file);
     } else {
-        hosts_file.save();
+        save_or_exit(&hosts_file, Action::Change);
+    }
+}
+
+fn save_or_exit(hosts_file: &ManagedHostsFile, action: Action) {
+    if let Err(ConflictError { file_name }) = hosts_file.save() {
+        eprintln!(
+            "Error: {} was changed by another program while hostman was running; nothing was saved. Please try again.",
+            file_name
+        );
+        std::process::exit(CONFLICT_EXIT_CODE);
+    }
+    record_change(hosts_file, action);
+}
+
+/// Appends the difference between the loaded and saved file to the journal.
+fn record_change(hosts_file: &ManagedHostsFile, action: Action) {
+    let old = hosts_file.loaded().lines().collect::<Vec<&str>>();
+    let contents = hosts_file.contents();
+    let hunks = diff::diff_lines(&old, &contents.lines().collect::<Vec<&str>>());
+    if hunks.is_empty() {
+        return;
+    }
+    let file_name = hosts_file.file_name();
+    let record = Record::new(&journal::load(file_name), action, hunks);
+    if let Err(e) = journal::append(file_name, &record) {
+        eprintln!(
+            "Warning: cannot write journal {}: {}",
+            journal::journal_path(file_name),
+            e
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_hosts(dir: &tempfile::TempDir, contents: &str) -> String {
+        let file_name = dir.path().join("hosts").display().to_string();
+        std::fs::write(&file_name, contents).unwrap();
+        file_name
+    }
+
+    fn read_hosts(file_name: &str) -> String {
+        std::fs::read_to_string(file_name).unwrap()
+    }
+
+    #[test]
+    fn run_removes_temporary_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let file_name = write_hosts(&dir, "127.0.0.1 localhost\n");
+        let add = vec![(String::from("10.0.0.1"), String::from("app.test,api.test"))];
+        let run = |script: &str| {
+            let command = vec![
+                String::from("sh"),
+                String::from("-c"),
+                script.replace("FILE", &file_name),
+            ];
+            let hosts_file = ManagedHostsFile::must_load_file_locked(&file_name);
+            run_with(hosts_file, &add, &command, false, None)
+        };
+
+        assert_eq!(
+            run("grep -q '^10.0.0.1 app.test api.test' FILE && exit 3"),
+            3
+        );
+        assert_eq!(read_hosts(&file_name), "127.0.0.1 localhost\n");
+        assert_eq!(run("kill -TERM $$"), 143);
+        assert_eq!(read_hosts(&file_name), "127.0.0.1 localhost\n");
+        assert_eq!(journal::load(&file_name).len(), 4);
+    }
+
+    #[test]
+    fn daemon_restores_wiped_file() {
+        let contents = "127.0.0.1 localhost\n\
+                        \n\
+                        # BEGIN hostman blocklist ads [source=file:///ads.txt]\n\
+                        0.0.0.0 tracker.test\n\
+                        # END hostman blocklist ads\n\
+                        10.0.0.1 app.test # Added by hostman\n";
+        let dir = tempfile::tempdir().unwrap();
+        let file_name = write_hosts(&dir, contents);
+        let hosts_file = ManagedHostsFile::from_file(&file_name).unwrap();
+        let mut status = daemon::Status {
+            pid: std::process::id(),
+            started: Utc::now(),
+            restored: 0,
+            last_restore: None,
+            enforce: None,
+            desired: drift::managed(&hosts_file),
+            sections: drift::managed_sections(&hosts_file),
+        };
+        let mut last_record = None;
+        for _ in 0..2 {
+            std::fs::write(&file_name, "127.0.0.1 localhost\n").unwrap();
+            daemon_tick(&file_name, false, &mut status, &mut last_record);
+            assert_eq!(read_hosts(&file_name), contents);
+        }
+        assert_eq!(status.restored, 4);
+        let hosts_file = ManagedHostsFile::from_file(&file_name).unwrap();
+        assert_eq!(
+            blocklist::lists(&hosts_file)[0].domains,
+            vec!["tracker.test"]
+        );
+    }
+
+    #[test]
+    fn reimport_leases() {
+        let lease = |ip: &str| Entry {
+            ip: String::from(ip),
+            names: vec![String::from("pc.lab")],
+            comment: Some(String::from("Added by hostman [source=/leases]")),
+            enabled: true,
+        };
+        let mut hosts_file = ManagedHostsFile::from_string(
+            "127.0.0.1 localhost\n\
+             10.0.0.5 pc.lab # Added by hostman [source=/leases]\n\
+             fd00::5 pc.lab # Added by hostman [source=/leases]\n",
+            "test",
+        );
+        let entries = vec![lease("10.0.0.6"), lease("fd00::5")];
+        let expected = "127.0.0.1 localhost\n\
+                        fd00::5 pc.lab # Added by hostman [source=/leases]\n\
+                        10.0.0.6 pc.lab # Added by hostman [source=/leases]\n";
+        remove_stale_leases(&mut hosts_file, &entries);
+        let summary = entry::import(&mut hosts_file, &entries, Conflict::Fail).unwrap();
+        assert_eq!((summary.added, summary.skipped), (1, 1));
+        assert_eq!(hosts_file.contents(), expected);
+
+        remove_stale_leases(&mut hosts_file, &entries);
+        let summary = entry::import(&mut hosts_file, &entries, Conflict::Fail).unwrap();
+        assert_eq!(summary.skipped, 2);
+        assert_eq!(hosts_file.contents(), expected);
+
+        let entries = vec![lease("10.0.0.6")];
+        remove_stale_leases(&mut hosts_file, &entries);
+        let summary = entry::import(&mut hosts_file, &entries, Conflict::Fail).unwrap();
+        assert_eq!(summary.skipped, 1);
+        assert_eq!(
+            hosts_file.contents(),
+            "127.0.0.1 localhost\n10.0.0.6 pc.lab # Added by hostman [source=/leases]\n"
+        );
     }
 }
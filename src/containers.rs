@@ -0,0 +1,114 @@
+use crate::dns::records;
+use crate::entry::Entry;
+use std::net::IpAddr;
+
+/// Every distinct name and ip pair of the enabled entries.
+fn mappings(entries: &[Entry]) -> Vec<(&str, IpAddr)> {
+    let mut mappings = vec![];
+    for (ip, e) in records(entries) {
+        for name in &e.names {
+            if !mappings.contains(&(name.as_str(), ip)) {
+                mappings.push((name.as_str(), ip));
+            }
+        }
+    }
+    mappings
+}
+
+/// `--add-host name:ip` flags for `docker run`, on a single line.
+pub fn docker(entries: &[Entry]) -> String {
+    let flags = mappings(entries)
+        .iter()
+        .map(|(name, ip)| format!("--add-host {}:{}", name, ip))
+        .collect::<Vec<String>>();
+    flags.join(" ") + "\n"
+}
+
+/// An `extra_hosts:` list for a docker-compose service.
+pub fn compose(entries: &[Entry]) -> String {
+    let mut out = vec![String::from("extra_hosts:")];
+    for (name, ip) in mappings(entries) {
+        out.push(format!("  - \"{}:{}\"", name, ip));
+    }
+    out.join("\n") + "\n"
+}
+
+/// A `hostAliases:` block for a Kubernetes pod spec, with one alias per ip.
+pub fn kubernetes(entries: &[Entry]) -> String {
+    let mut aliases: Vec<(IpAddr, Vec<&str>)> = vec![];
+    for (ip, e) in records(entries) {
+        let index = match aliases.iter().position(|(i, _)| *i == ip) {
+            Some(index) => index,
+            None => {
+                aliases.push((ip, vec![]));
+                aliases.len() - 1
+            }
+        };
+        let names = &mut aliases[index].1;
+        for name in &e.names {
+            if !names.contains(&name.as_str()) {
+                names.push(name);
+            }
+        }
+    }
+    let mut out = vec![String::from("hostAliases:")];
+    for (ip, names) in aliases {
+        out.push(format!("  - ip: \"{}\"", ip));
+        out.push(String::from("    hostnames:"));
+        out.extend(names.iter().map(|n| format!("      - \"{}\"", n)));
+    }
+    out.join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries() -> Vec<Entry> {
+        let entry = |ip: &str, names: &[&str], enabled| Entry {
+            ip: String::from(ip),
+            names: names.iter().map(|n| String::from(*n)).collect(),
+            comment: None,
+            enabled,
+        };
+        vec![
+            entry("10.0.0.1", &["a.test", "b.test"], true),
+            entry("10.0.0.2", &["off.test"], false),
+            entry("::1", &["c.test"], true),
+            entry("10.0.0.1", &["b.test", "d.test"], true),
+        ]
+    }
+
+    #[test]
+    fn docker_flags() {
+        assert_eq!(
+            docker(&entries()),
+            "--add-host a.test:10.0.0.1 --add-host b.test:10.0.0.1 --add-host c.test:::1 \
+             --add-host d.test:10.0.0.1\n"
+        );
+    }
+
+    #[test]
+    fn compose_extra_hosts() {
+        assert_eq!(
+            compose(&entries()[..3]),
+            "extra_hosts:\n  - \"a.test:10.0.0.1\"\n  - \"b.test:10.0.0.1\"\n  - \"c.test:::1\"\n"
+        );
+    }
+
+    #[test]
+    fn kubernetes_host_aliases() {
+        assert_eq!(
+            kubernetes(&entries()),
+            "hostAliases:\n\
+             \x20 - ip: \"10.0.0.1\"\n\
+             \x20   hostnames:\n\
+             \x20     - \"a.test\"\n\
+             \x20     - \"b.test\"\n\
+             \x20     - \"d.test\"\n\
+             \x20 - ip: \"::1\"\n\
+             \x20   hostnames:\n\
+             \x20     - \"c.test\"\n"
+        );
+    }
+}
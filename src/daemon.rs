@@ -0,0 +1,147 @@
+use crate::drift;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// What a running daemon last did, kept next to the hosts file for
+/// `hostman daemon status`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Status {
+    pub pid: u32,
+    pub started: DateTime<Utc>,
+    pub restored: usize,
+    pub last_restore: Option<DateTime<Utc>>,
+    pub enforce: Option<String>,
+    /// The lines the daemon keeps in the file.
+    pub desired: Vec<String>,
+    /// The sections the daemon keeps in the file, markers included.
+    #[serde(default)]
+    pub sections: Vec<drift::Section>,
+}
+
+pub fn status_path(file_name: &str) -> String {
+    format!("{}.daemon", file_name)
+}
+
+pub fn load_status(file_name: &str) -> Option<Status> {
+    let contents = std::fs::read_to_string(status_path(file_name)).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+pub fn save_status(file_name: &str, status: &Status) -> Result<(), std::io::Error> {
+    std::fs::write(
+        status_path(file_name),
+        serde_json::to_string_pretty(status)?,
+    )
+}
+
+/// Whether a process with the given id is running.
+#[cfg(unix)]
+pub fn is_running(pid: u32) -> bool {
+    let alive = unsafe { libc::kill(pid as libc::pid_t, 0) } == 0;
+    alive || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+pub fn is_running(_pid: u32) -> bool {
+    true
+}
+
+/// Watches files for changes. The directories are watched rather than the
+/// files, so files replaced by renaming them over are still seen.
+#[cfg(target_os = "linux")]
+pub struct Watcher {
+    inotify: inotify::Inotify,
+    names: Vec<std::ffi::OsString>,
+    buffer: [u8; 4096],
+}
+
+#[cfg(target_os = "linux")]
+impl Watcher {
+    pub fn new(files: &[&str]) -> Result<Watcher, String> {
+        use inotify::{Inotify, WatchMask};
+        let mut inotify = Inotify::init().map_err(|e| e.to_string())?;
+        let mut names = vec![];
+        for file in files {
+            let path = std::path::Path::new(file);
+            let dir = match path.parent() {
+                Some(dir) if dir.as_os_str().is_empty() => std::path::Path::new("."),
+                Some(dir) => dir,
+                None => continue,
+            };
+            let mask = WatchMask::CLOSE_WRITE
+                | WatchMask::MOVED_TO
+                | WatchMask::CREATE
+                | WatchMask::DELETE;
+            inotify
+                .add_watch(dir, mask)
+                .map_err(|e| format!("{}: {}", dir.display(), e))?;
+            names.extend(path.file_name().map(|n| n.to_os_string()));
+        }
+        Ok(Watcher {
+            inotify,
+            names,
+            buffer: [0; 4096],
+        })
+    }
+
+    /// Waits until a file changes or `timeout` passes, and returns whether a
+    /// file changed. After a change it waits until the files have been quiet
+    /// for `debounce`, so a burst of writes counts once.
+    pub fn wait(&mut self, timeout: Duration, debounce: Duration) -> bool {
+        let deadline = std::time::Instant::now() + timeout;
+        loop {
+            let left = deadline.saturating_duration_since(std::time::Instant::now());
+            if left.is_zero() {
+                return false;
+            }
+            if self.poll(left) && self.changed() {
+                break;
+            }
+        }
+        while self.poll(debounce) {
+            self.changed();
+        }
+        true
+    }
+
+    fn poll(&self, timeout: Duration) -> bool {
+        use std::os::unix::io::AsRawFd;
+        let mut fd = libc::pollfd {
+            fd: self.inotify.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = timeout.as_millis().min(i32::MAX as u128) as i32;
+        unsafe { libc::poll(&mut fd, 1, timeout) > 0 }
+    }
+
+    /// Reads the pending events, returning whether one is about a watched file.
+    fn changed(&mut self) -> bool {
+        let names = &self.names;
+        match self.inotify.read_events(&mut self.buffer) {
+            Ok(events) => {
+                events
+                    .filter(|e| e.name.is_some_and(|n| names.iter().any(|m| m == n)))
+                    .count()
+                    > 0
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub struct Watcher;
+
+#[cfg(not(target_os = "linux"))]
+impl Watcher {
+    pub fn new(_files: &[&str]) -> Result<Watcher, String> {
+        Err(String::from("watching files is only supported on Linux"))
+    }
+
+    pub fn wait(&mut self, timeout: Duration, _debounce: Duration) -> bool {
+        std::thread::sleep(timeout);
+        false
+    }
+}
@@ -0,0 +1,279 @@
+use serde::{Deserialize, Serialize};
+
+/// How many unchanged lines around a hunk are kept to find it again.
+const CONTEXT: usize = 2;
+/// Above this many cells the LCS table is skipped and the changed region is
+/// reported as a single replacement.
+const MAX_LCS_CELLS: usize = 4_000_000;
+
+/// A contiguous change: `removed` lines starting at `start` in the old file were
+/// replaced by `added`. `before` and `after` are the unchanged lines around it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hunk {
+    pub start: usize,
+    pub removed: Vec<String>,
+    pub added: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub before: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub after: Vec<String>,
+}
+
+/// The affected lines no longer match what the change expects.
+#[derive(Debug, PartialEq, Eq)]
+pub struct DivergedError {
+    pub line: usize,
+}
+
+pub fn diff_lines(old: &[&str], new: &[&str]) -> Vec<Hunk> {
+    let prefix = old
+        .iter()
+        .zip(new.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+    if old_mid.is_empty() && new_mid.is_empty() {
+        return vec![];
+    }
+    if (old_mid.len() + 1) * (new_mid.len() + 1) > MAX_LCS_CELLS {
+        let hunk = Hunk {
+            start: prefix,
+            removed: to_strings(old_mid),
+            added: to_strings(new_mid),
+            ..Hunk::default()
+        };
+        return with_context(old, vec![hunk]);
+    }
+
+    // lcs[i][j] is the LCS length of old_mid[i..] and new_mid[j..].
+    let (n, m) = (old_mid.len(), new_mid.len());
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if old_mid[i] == new_mid[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut hunks = vec![];
+    let mut current: Option<Hunk> = None;
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && old_mid[i] == new_mid[j] {
+            hunks.extend(current.take());
+            i += 1;
+            j += 1;
+            continue;
+        }
+        let hunk = current.get_or_insert_with(|| Hunk {
+            start: prefix + i,
+            ..Hunk::default()
+        });
+        if j < m && (i == n || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            hunk.added.push(String::from(new_mid[j]));
+            j += 1;
+        } else {
+            hunk.removed.push(String::from(old_mid[i]));
+            i += 1;
+        }
+    }
+    hunks.extend(current);
+    with_context(old, hunks)
+}
+
+/// Sets the context of each hunk to the unchanged lines of `old` around it, up
+/// to the neighbouring hunks. Unchanged lines are the same in the new file, so
+/// the context holds for the inverted hunks too.
+fn with_context(old: &[&str], mut hunks: Vec<Hunk>) -> Vec<Hunk> {
+    for k in 0..hunks.len() {
+        let previous_end = match k {
+            0 => 0,
+            _ => hunks[k - 1].start + hunks[k - 1].removed.len(),
+        };
+        let next_start = hunks.get(k + 1).map_or(old.len(), |h| h.start);
+        let hunk = &mut hunks[k];
+        let end = hunk.start + hunk.removed.len();
+        let before_start = hunk.start.saturating_sub(CONTEXT).max(previous_end);
+        hunk.before = to_strings(&old[before_start..hunk.start]);
+        hunk.after = to_strings(&old[end..(end + CONTEXT).min(next_start)]);
+    }
+    hunks
+}
+
+/// Returns the hunks that turn the new file back into the old one.
+pub fn invert(hunks: &[Hunk]) -> Vec<Hunk> {
+    let mut offset: isize = 0;
+    hunks
+        .iter()
+        .map(|h| {
+            let start = (h.start as isize + offset) as usize;
+            offset += h.added.len() as isize - h.removed.len() as isize;
+            Hunk {
+                start,
+                removed: h.added.clone(),
+                added: h.removed.clone(),
+                before: h.before.clone(),
+                after: h.after.clone(),
+            }
+        })
+        .collect()
+}
+
+/// Applies hunks to `lines`. Each hunk goes where the lines it removes and its
+/// context are, nearest to where it was recorded, so that changes elsewhere in
+/// the file don't get in the way. Fails if a hunk's lines are nowhere to be
+/// found. Lines are compared ignoring differences in whitespace.
+pub fn apply(lines: &[&str], hunks: &[Hunk]) -> Result<Vec<String>, DivergedError> {
+    let mut result = vec![];
+    let mut next = 0;
+    let mut offset: isize = 0;
+    for hunk in hunks {
+        let expected = ((hunk.start as isize + offset).max(0) as usize).max(next);
+        let start = (next..=lines.len())
+            .filter(|p| fits(lines, *p, hunk))
+            .min_by_key(|p| p.abs_diff(expected))
+            .ok_or(DivergedError {
+                line: expected.min(lines.len()),
+            })?;
+        offset = start as isize - hunk.start as isize;
+        result.extend(to_strings(&lines[next..start]));
+        result.extend(hunk.added.iter().cloned());
+        next = start + hunk.removed.len();
+    }
+    result.extend(to_strings(&lines[next..]));
+    Ok(result)
+}
+
+/// Whether the lines a hunk removes, and its context, are at `start`.
+fn fits(lines: &[&str], start: usize, hunk: &Hunk) -> bool {
+    let end = start + hunk.removed.len();
+    start >= hunk.before.len()
+        && end + hunk.after.len() <= lines.len()
+        && same(&lines[start - hunk.before.len()..start], &hunk.before)
+        && same(&lines[start..end], &hunk.removed)
+        && same(&lines[end..end + hunk.after.len()], &hunk.after)
+}
+
+fn same(lines: &[&str], expected: &[String]) -> bool {
+    lines
+        .iter()
+        .zip(expected.iter())
+        .all(|(a, b)| normalize(a) == normalize(b))
+}
+
+/// Collapses runs of whitespace so that equivalent hosts lines compare equal.
+pub fn normalize(line: &str) -> String {
+    line.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+fn to_strings(lines: &[&str]) -> Vec<String> {
+    lines.iter().map(|l| String::from(*l)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_and_apply() {
+        let old = vec![
+            "# hosts",
+            "127.0.0.1 localhost",
+            "10.0.0.1 a.test",
+            "::1 localhost",
+        ];
+        let new = vec![
+            "# hosts",
+            "127.0.0.1 localhost",
+            "10.0.0.2 b.test",
+            "::1 localhost",
+            "10.0.0.3 c.test",
+        ];
+        let hunks = diff_lines(&old, &new);
+        assert_eq!(
+            hunks,
+            vec![
+                Hunk {
+                    start: 2,
+                    removed: vec![String::from("10.0.0.1 a.test")],
+                    added: vec![String::from("10.0.0.2 b.test")],
+                    before: vec![String::from("# hosts"), String::from("127.0.0.1 localhost")],
+                    after: vec![String::from("::1 localhost")],
+                },
+                Hunk {
+                    start: 4,
+                    removed: vec![],
+                    added: vec![String::from("10.0.0.3 c.test")],
+                    before: vec![String::from("::1 localhost")],
+                    after: vec![],
+                },
+            ]
+        );
+        assert_eq!(apply(&old, &hunks).unwrap(), new);
+        assert_eq!(apply(&new, &invert(&hunks)).unwrap(), old);
+        assert!(diff_lines(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn apply_ignores_whitespace() {
+        let hunks = diff_lines(
+            &["127.0.0.1\tlocalhost"],
+            &["127.0.0.1 localhost", "10.0.0.1 a.test"],
+        );
+        let current = vec!["127.0.0.1 localhost", "10.0.0.1 a.test"];
+        assert_eq!(
+            apply(&current, &invert(&hunks)).unwrap(),
+            vec!["127.0.0.1\tlocalhost"]
+        );
+    }
+
+    #[test]
+    fn apply_finds_moved_hunks() {
+        let old = vec!["127.0.0.1 localhost", "::1 localhost"];
+        let new = vec!["127.0.0.1 localhost", "10.0.0.1 a.test", "::1 localhost"];
+        let hunks = diff_lines(&old, &new);
+        let current = vec![
+            "# added by hand",
+            "10.0.0.9 other.test",
+            "127.0.0.1 localhost",
+            "10.0.0.1 a.test",
+            "::1 localhost",
+            "10.0.0.1 a.test",
+        ];
+        assert_eq!(
+            apply(&current, &invert(&hunks)).unwrap(),
+            vec![
+                "# added by hand",
+                "10.0.0.9 other.test",
+                "127.0.0.1 localhost",
+                "::1 localhost",
+                "10.0.0.1 a.test",
+            ]
+        );
+        let current = vec!["# added by hand", "127.0.0.1 localhost", "::1 localhost"];
+        assert_eq!(apply(&current, &hunks).unwrap()[2], "10.0.0.1 a.test");
+    }
+
+    #[test]
+    fn apply_detects_divergence() {
+        let hunks = diff_lines(&["a", "b"], &["a", "c"]);
+        assert_eq!(
+            apply(&["a", "d"], &invert(&hunks)),
+            Err(DivergedError { line: 1 })
+        );
+        assert_eq!(
+            apply(&["a"], &invert(&hunks)),
+            Err(DivergedError { line: 1 })
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use crate::entry::Entry;
+use std::net::IpAddr;
+
+const HEADER: &str = "# Generated by hostman, do not edit.";
+
+/// Returns the enabled entries with a plain (unscoped) address.
+pub fn records(entries: &[Entry]) -> Vec<(IpAddr, &Entry)> {
+    entries
+        .iter()
+        .filter(|e| e.enabled)
+        .filter_map(|e| e.ip.parse().ok().map(|ip| (ip, e)))
+        .collect()
+}
+
+/// dnsmasq `host-record=` lines, or `address=/name/ip` lines with `wildcard`,
+/// which also answer for every subdomain.
+pub fn dnsmasq(entries: &[Entry], wildcard: bool) -> String {
+    let mut out = vec![String::from(HEADER)];
+    for (ip, e) in records(entries) {
+        if wildcard {
+            out.extend(e.names.iter().map(|n| format!("address=/{}/{}", n, ip)));
+        } else {
+            out.push(format!("host-record={},{}", e.names.join(","), ip));
+        }
+    }
+    out.join("\n") + "\n"
+}
+
+/// An Unbound `server:` clause with `local-data:` records and one reverse record
+/// per address. With `wildcard`, each name gets a redirect zone so its
+/// subdomains resolve to the same address.
+pub fn unbound(entries: &[Entry], wildcard: bool) -> String {
+    let mut out = vec![String::from(HEADER), String::from("server:")];
+    let mut zones: Vec<&str> = vec![];
+    let mut pointers: Vec<IpAddr> = vec![];
+    for (ip, e) in records(entries) {
+        let kind = if ip.is_ipv4() { "A" } else { "AAAA" };
+        for name in &e.names {
+            if wildcard && !zones.contains(&name.as_str()) {
+                zones.push(name);
+                out.push(format!("    local-zone: \"{}.\" redirect", name));
+            }
+            out.push(format!("    local-data: \"{}. IN {} {}\"", name, kind, ip));
+        }
+        if let Some(name) = e.names.first().filter(|_| !pointers.contains(&ip)) {
+            pointers.push(ip);
+            out.push(format!("    local-data-ptr: \"{} {}.\"", ip, name));
+        }
+    }
+    out.join("\n") + "\n"
+}
+
+/// A CoreDNS `hosts` plugin block that passes other queries on to the next plugin.
+pub fn coredns(entries: &[Entry]) -> String {
+    let mut out = vec![String::from(HEADER), String::from("hosts {")];
+    for (ip, e) in records(entries) {
+        out.push(format!("    {} {}", ip, e.names.join(" ")));
+    }
+    out.push(String::from("    fallthrough"));
+    out.push(String::from("}"));
+    out.join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries() -> Vec<Entry> {
+        let entry = |ip: &str, names: &[&str], enabled| Entry {
+            ip: String::from(ip),
+            names: names.iter().map(|n| String::from(*n)).collect(),
+            comment: None,
+            enabled,
+        };
+        vec![
+            entry("10.0.0.1", &["a.test", "b.test"], true),
+            entry("fe80::1%lo0", &["scoped.test"], true),
+            entry("10.0.0.2", &["off.test"], false),
+            entry("::1", &["a.test"], true),
+            entry("10.0.0.1", &["c.test"], true),
+        ]
+    }
+
+    #[test]
+    fn dnsmasq_records() {
+        assert_eq!(
+            dnsmasq(&entries(), false),
+            format!(
+                "{}\nhost-record=a.test,b.test,10.0.0.1\nhost-record=a.test,::1\nhost-record=c.test,10.0.0.1\n",
+                HEADER
+            )
+        );
+        assert_eq!(
+            dnsmasq(&entries(), true),
+            format!(
+                "{}\naddress=/a.test/10.0.0.1\naddress=/b.test/10.0.0.1\naddress=/a.test/::1\naddress=/c.test/10.0.0.1\n",
+                HEADER
+            )
+        );
+    }
+
+    #[test]
+    fn unbound_records() {
+        let config = unbound(&entries(), true);
+        assert!(config.contains("    local-data: \"b.test. IN A 10.0.0.1\"\n"));
+        assert!(config.contains("    local-data: \"a.test. IN AAAA ::1\"\n"));
+        assert!(config.contains("    local-data-ptr: \"10.0.0.1 a.test.\"\n"));
+        assert_eq!(config.matches("local-zone: \"a.test.\"").count(), 1);
+        assert_eq!(config.matches("local-data-ptr: \"10.0.0.1 ").count(), 1);
+        assert!(!config.contains("off.test"));
+        assert!(!unbound(&entries(), false).contains("local-zone"));
+    }
+
+    #[test]
+    fn coredns_block() {
+        assert_eq!(
+            coredns(&entries()),
+            format!(
+                "{}\nhosts {{\n    10.0.0.1 a.test b.test\n    ::1 a.test\n    10.0.0.1 c.test\n    fallthrough\n}}\n",
+                HEADER
+            )
+        );
+    }
+}
@@ -0,0 +1,237 @@
+use crate::blocklist;
+use crate::hostsfile::ManagedHostsFile;
+use crate::metadata;
+use hosts_parser::HostsFileLine;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// A section of the file that is kept as a whole, with the comment of its begin
+/// marker and its lines as they are written in the file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Section {
+    pub name: String,
+    pub marker: String,
+    pub lines: Vec<String>,
+}
+
+/// Returns the enabled and disabled lines hostman added, as they are written in
+/// the file. The lines of blocklists are kept with their section instead.
+pub fn managed(hosts_file: &ManagedHostsFile) -> Vec<String> {
+    hosts_file
+        .entries()
+        .into_iter()
+        .filter(|(i, _)| {
+            let comment = hosts_file.entry_at(*i).and_then(|l| l.comment());
+            comment.is_some_and(|c| metadata::is_managed(&c))
+        })
+        .map(|(i, _)| hosts_file.line(i).to_string())
+        .collect()
+}
+
+/// Returns the blocklist sections of the file.
+pub fn managed_sections(hosts_file: &ManagedHostsFile) -> Vec<Section> {
+    hosts_file
+        .sections()
+        .into_iter()
+        .filter(|(name, _)| blocklist::is_section(name))
+        .filter_map(|(name, marker)| section(hosts_file, name, marker))
+        .collect()
+}
+
+fn section(hosts_file: &ManagedHostsFile, name: String, marker: String) -> Option<Section> {
+    let range = hosts_file.section(&name)?;
+    let lines = hosts_file.lines()[*range.start() + 1..*range.end()]
+        .iter()
+        .map(|l| l.to_string())
+        .collect();
+    Some(Section {
+        name,
+        marker,
+        lines,
+    })
+}
+
+/// Returns the desired sections that are missing from the file or differ.
+pub fn changed_sections<'a>(
+    hosts_file: &ManagedHostsFile,
+    desired: &'a [Section],
+) -> Vec<&'a Section> {
+    let current = hosts_file.sections();
+    desired
+        .iter()
+        .filter(|s| {
+            let found = current.iter().find(|(name, _)| *name == s.name);
+            found
+                .and_then(|(name, marker)| section(hosts_file, name.clone(), marker.clone()))
+                .as_ref()
+                != Some(*s)
+        })
+        .collect()
+}
+
+/// Puts the missing or changed desired sections back, markers included, in
+/// place or at the end of the file. Returns the restored sections.
+pub fn restore_sections<'a>(
+    hosts_file: &mut ManagedHostsFile,
+    desired: &'a [Section],
+) -> Vec<&'a Section> {
+    let changed = changed_sections(hosts_file, desired);
+    for section in &changed {
+        let lines = section
+            .lines
+            .iter()
+            .filter_map(|l| HostsFileLine::from_string(l).ok())
+            .collect();
+        hosts_file.put_section(&section.name, &section.marker, lines);
+    }
+    changed
+}
+
+/// Returns the entries of an enforcement file, which uses the hosts file format.
+pub fn parse_enforced(contents: &str) -> Result<Vec<String>, String> {
+    let hosts_file = ManagedHostsFile::try_from_string(contents, "").map_err(|e| e.to_string())?;
+    Ok(hosts_file
+        .without_comments()
+        .into_iter()
+        .map(|l| l.to_string())
+        .collect())
+}
+
+/// Returns the desired lines that are not in the file.
+pub fn missing(hosts_file: &ManagedHostsFile, desired: &[String]) -> Vec<String> {
+    let mut present: HashSet<String> = hosts_file.lines().iter().map(|l| l.to_string()).collect();
+    desired
+        .iter()
+        .filter(|l| present.insert(l.to_string()))
+        .cloned()
+        .collect()
+}
+
+/// Adds the missing desired lines back at the end of the file. Their names are
+/// first taken off the other lines of the same address family, which are
+/// removed if no name is left. Returns the restored lines.
+pub fn restore(hosts_file: &mut ManagedHostsFile, desired: &[String]) -> Vec<String> {
+    let missing = missing(hosts_file, desired);
+    let restored = ManagedHostsFile::from_string(&missing.join("\n"), "");
+    let taken: HashSet<(bool, String)> = restored
+        .entries()
+        .into_iter()
+        .filter_map(|(i, _)| restored.entry_at(i))
+        .flat_map(|e| {
+            let is_v6 = e.ip().unwrap_or_default().contains(':');
+            e.hosts().into_iter().map(move |name| (is_v6, name))
+        })
+        .collect();
+    let desired: HashSet<&String> = desired.iter().collect();
+    for (index, _) in hosts_file.entries().into_iter().rev() {
+        if desired.contains(&hosts_file.line(index).to_string()) {
+            continue;
+        }
+        let entry = hosts_file.entry_at(index).unwrap();
+        let is_v6 = entry.ip().unwrap_or_default().contains(':');
+        let names = entry
+            .hosts()
+            .into_iter()
+            .filter(|name| taken.contains(&(is_v6, name.clone())))
+            .collect::<Vec<String>>();
+        if !names.is_empty() {
+            hosts_file.remove_names_at(index, &names);
+        }
+    }
+    for line in &missing {
+        hosts_file.add_line(line);
+    }
+    missing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn managed_and_enforced_lines() {
+        let hf = ManagedHostsFile::from_string(
+            "127.0.0.1 localhost\n\
+             10.0.0.1 app.test # my app [by=hostman]\n\
+             #0.0.0.0 ads.test # Blocked by hostman [block=ads.test]\n\
+             10.0.0.2 other.test # Added by hostman\n\
+             10.0.0.3 mine.test # mine\n\
+             # BEGIN hostman blocklist ads [source=file:///ads.txt]\n\
+             0.0.0.0 tracker.test\n\
+             # END hostman blocklist ads\n",
+            "test",
+        );
+        assert_eq!(
+            managed(&hf),
+            vec![
+                "10.0.0.1 app.test # my app [by=hostman]",
+                "#0.0.0.0 ads.test # Blocked by hostman [block=ads.test]",
+                "10.0.0.2 other.test # Added by hostman"
+            ]
+        );
+        assert_eq!(
+            managed_sections(&hf),
+            vec![Section {
+                name: String::from("hostman blocklist ads"),
+                marker: String::from("# BEGIN hostman blocklist ads [source=file:///ads.txt]"),
+                lines: vec![String::from("0.0.0.0 tracker.test")],
+            }]
+        );
+        assert_eq!(
+            parse_enforced("# company\n10.1.0.1   vpn.test\n\n").unwrap(),
+            vec!["10.1.0.1 vpn.test"]
+        );
+        assert!(parse_enforced("10.1.0.1\n").is_err());
+    }
+
+    #[test]
+    fn restore_drift() {
+        let desired = vec![
+            String::from("10.0.0.1 app.test # Added by hostman"),
+            String::from("10.1.0.1 vpn.test"),
+            String::from("10.1.0.1 vpn.test"),
+        ];
+        let mut hf = ManagedHostsFile::from_string(
+            "127.0.0.1 localhost\n\
+             10.9.9.9 app.test api.test\n\
+             fd00::1 app.test\n\
+             10.9.9.8 vpn.test\n",
+            "test",
+        );
+        assert_eq!(restore(&mut hf, &desired), &desired[..2]);
+        assert_eq!(
+            hf.contents(),
+            "127.0.0.1 localhost\n\
+             10.9.9.9 api.test\n\
+             fd00::1 app.test\n\
+             10.0.0.1 app.test # Added by hostman\n\
+             10.1.0.1 vpn.test\n"
+        );
+        assert!(restore(&mut hf, &desired).is_empty());
+    }
+
+    #[test]
+    fn restore_wiped_sections() {
+        let contents = "127.0.0.1 localhost\n\
+                        \n\
+                        # BEGIN hostman blocklist ads [source=file:///ads.txt]\n\
+                        0.0.0.0 tracker.test\n\
+                        # END hostman blocklist ads\n";
+        let desired = managed_sections(&ManagedHostsFile::from_string(contents, "test"));
+        let mut hf = ManagedHostsFile::from_string("127.0.0.1 localhost\n", "test");
+        assert_eq!(restore_sections(&mut hf, &desired).len(), 1);
+        assert_eq!(hf.contents(), contents);
+        assert!(restore_sections(&mut hf, &desired).is_empty());
+
+        let mut hf = ManagedHostsFile::from_string(
+            "127.0.0.1 localhost\n\
+             \n\
+             # BEGIN hostman blocklist ads [source=file:///ads.txt]\n\
+             # END hostman blocklist ads\n",
+            "test",
+        );
+        assert_eq!(changed_sections(&hf, &desired).len(), 1);
+        restore_sections(&mut hf, &desired);
+        assert_eq!(hf.contents(), contents);
+    }
+}
@@ -0,0 +1,434 @@
+use crate::containers;
+use crate::dns;
+use crate::hostsfile::{self, ManagedHostsFile, MatchType};
+use crate::metadata;
+use hosts_parser::HostsFileLine;
+use serde::{Deserialize, Serialize};
+
+/// A hosts entry in a form that can be exported and imported.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub ip: String,
+    pub names: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+/// Whether a name is one the system defines for itself, like `localhost` or
+/// `ip6-allnodes`.
+fn is_system_name(name: &str) -> bool {
+    name == "localhost"
+        || name == "localhost.localdomain"
+        || name == "broadcasthost"
+        || name.starts_with("ip6-")
+}
+
+impl Entry {
+    pub fn from_line(line: &HostsFileLine, enabled: bool) -> Entry {
+        Entry {
+            ip: line.ip().unwrap_or_default(),
+            names: line.hosts(),
+            comment: line
+                .comment()
+                .map(|c| metadata::without_tag(c.trim_start_matches('#'), metadata::MANAGED_TAG))
+                .filter(|c| !c.is_empty()),
+            enabled,
+        }
+    }
+
+    /// Whether the pattern appears in the ip, a name or the comment, ignoring case.
+    pub fn matches(&self, pattern: &str) -> bool {
+        let pattern = pattern.to_lowercase();
+        std::iter::once(&self.ip)
+            .chain(self.names.iter())
+            .chain(self.comment.iter())
+            .any(|field| field.to_lowercase().contains(&pattern))
+    }
+
+    /// Returns the entry without the names the system defines for itself, or
+    /// `None` if no other name is left.
+    pub fn without_system_names(mut self) -> Option<Entry> {
+        self.names.retain(|n| !is_system_name(n));
+        if self.names.is_empty() {
+            None
+        } else {
+            Some(self)
+        }
+    }
+}
+
+/// Returns every enabled and disabled entry of the file, in order.
+pub fn entries(hosts_file: &ManagedHostsFile) -> Vec<Entry> {
+    hosts_file
+        .entries()
+        .into_iter()
+        .filter_map(|(i, enabled)| {
+            hosts_file
+                .entry_at(i)
+                .map(|line| Entry::from_line(&line, enabled))
+        })
+        .collect()
+}
+
+/// Returns the entries whose line matches `host`, like `check` does.
+pub fn matching(hosts_file: &ManagedHostsFile, host: &str, exact: &MatchType) -> Vec<Entry> {
+    hosts_file
+        .entries()
+        .into_iter()
+        .filter(|(i, _)| hostsfile::matches(hosts_file.line(*i), host, exact))
+        .filter_map(|(i, enabled)| {
+            hosts_file
+                .entry_at(i)
+                .map(|line| Entry::from_line(&line, enabled))
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Yaml,
+    Toml,
+    Csv,
+    Dnsmasq,
+    Unbound,
+    Coredns,
+    Zone,
+    Docker,
+    Compose,
+    K8s,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "json" => Ok(Format::Json),
+            "yaml" | "yml" => Ok(Format::Yaml),
+            "toml" => Ok(Format::Toml),
+            "csv" => Ok(Format::Csv),
+            "dnsmasq" => Ok(Format::Dnsmasq),
+            "unbound" => Ok(Format::Unbound),
+            "coredns" => Ok(Format::Coredns),
+            "zone" => Ok(Format::Zone),
+            "docker" => Ok(Format::Docker),
+            "compose" => Ok(Format::Compose),
+            "k8s" | "kubernetes" => Ok(Format::K8s),
+            _ => Err(format!("unknown format: {}", s)),
+        }
+    }
+}
+
+impl Format {
+    /// Guesses the format from a file extension.
+    pub fn from_path(path: &str) -> Option<Format> {
+        std::path::Path::new(path)
+            .extension()?
+            .to_str()?
+            .to_lowercase()
+            .parse()
+            .ok()
+    }
+}
+
+/// TOML documents need a table at the top level.
+#[derive(Serialize, Deserialize)]
+struct TomlEntries {
+    #[serde(default)]
+    entry: Vec<Entry>,
+}
+
+/// A CSV row, with names separated by spaces.
+#[derive(Serialize, Deserialize)]
+struct CsvEntry {
+    ip: String,
+    names: String,
+    #[serde(default)]
+    comment: String,
+    #[serde(default = "enabled")]
+    enabled: bool,
+}
+
+/// Writes the entries in `format`. Resolver and container formats only include
+/// enabled entries; with `wildcard`, dnsmasq and Unbound names also match
+/// subdomains.
+pub fn serialize(entries: &[Entry], format: Format, wildcard: bool) -> Result<String, String> {
+    if wildcard && !matches!(format, Format::Dnsmasq | Format::Unbound) {
+        return Err(String::from(
+            "wildcards are only supported by the dnsmasq and unbound formats",
+        ));
+    }
+    match format {
+        Format::Dnsmasq => Ok(dns::dnsmasq(entries, wildcard)),
+        Format::Unbound => Ok(dns::unbound(entries, wildcard)),
+        Format::Coredns => Ok(dns::coredns(entries)),
+        Format::Docker => Ok(containers::docker(entries)),
+        Format::Compose => Ok(containers::compose(entries)),
+        Format::K8s => Ok(containers::kubernetes(entries)),
+        Format::Zone => Err(String::from(
+            "zone files need an origin, please pass --origin",
+        )),
+        Format::Json => serde_json::to_string_pretty(entries)
+            .map(|s| s + "\n")
+            .map_err(|e| e.to_string()),
+        Format::Yaml => serde_yaml::to_string(entries).map_err(|e| e.to_string()),
+        Format::Toml => toml::to_string(&TomlEntries {
+            entry: entries.to_vec(),
+        })
+        .map_err(|e| e.to_string()),
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(vec![]);
+            for e in entries {
+                writer
+                    .serialize(CsvEntry {
+                        ip: e.ip.clone(),
+                        names: e.names.join(" "),
+                        comment: e.comment.clone().unwrap_or_default(),
+                        enabled: e.enabled,
+                    })
+                    .map_err(|e| e.to_string())?;
+            }
+            let bytes = writer.into_inner().map_err(|e| e.to_string())?;
+            String::from_utf8(bytes).map_err(|e| e.to_string())
+        }
+    }
+}
+
+pub fn deserialize(contents: &str, format: Format) -> Result<Vec<Entry>, String> {
+    match format {
+        Format::Json => serde_json::from_str(contents).map_err(|e| e.to_string()),
+        Format::Yaml => serde_yaml::from_str(contents).map_err(|e| e.to_string()),
+        Format::Toml => toml::from_str::<TomlEntries>(contents)
+            .map(|t| t.entry)
+            .map_err(|e| e.to_string()),
+        Format::Csv => csv::Reader::from_reader(contents.as_bytes())
+            .deserialize::<CsvEntry>()
+            .map(|row| {
+                row.map(|row| Entry {
+                    ip: row.ip,
+                    names: row.names.split_whitespace().map(String::from).collect(),
+                    comment: Some(row.comment).filter(|c| !c.is_empty()),
+                    enabled: row.enabled,
+                })
+                .map_err(|e| e.to_string())
+            })
+            .collect(),
+        _ => Err(format!("{:?} files cannot be imported", format)),
+    }
+}
+
+/// What to do with an imported entry whose names are already in the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Conflict {
+    Skip,
+    Overwrite,
+    #[default]
+    Fail,
+}
+
+impl std::str::FromStr for Conflict {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Conflict, String> {
+        match s {
+            "skip" => Ok(Conflict::Skip),
+            "overwrite" => Ok(Conflict::Overwrite),
+            "fail" => Ok(Conflict::Fail),
+            _ => Err(format!("unknown conflict strategy: {}", s)),
+        }
+    }
+}
+
+/// How many imported entries were added, replaced existing ones, or skipped.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    pub added: usize,
+    pub replaced: usize,
+    pub skipped: usize,
+}
+
+/// Adds the entries to the file. Entries that are already present are skipped;
+/// entries that share a name with existing ones are handled as `on_conflict` says.
+/// Entries of the same import do not conflict with each other, so a name can
+/// be imported with both an IPv4 and an IPv6 address.
+pub fn import(
+    hosts_file: &mut ManagedHostsFile,
+    entries: &[Entry],
+    on_conflict: Conflict,
+) -> Result<ImportSummary, String> {
+    let mut summary = ImportSummary::default();
+    let existing = self::entries(hosts_file);
+    for entry in entries {
+        if existing.contains(entry) {
+            summary.skipped += 1;
+            continue;
+        }
+        let mut conflicts = entry
+            .names
+            .iter()
+            .flat_map(|n| hosts_file.find_entries(n))
+            .filter(|i| {
+                let line = hosts_file.entry_at(*i).unwrap();
+                !entries.contains(&Entry::from_line(&line, hosts_file.line(*i).has_host()))
+            })
+            .collect::<Vec<usize>>();
+        conflicts.sort_unstable();
+        conflicts.dedup();
+        if !conflicts.is_empty() {
+            match on_conflict {
+                Conflict::Skip => {
+                    summary.skipped += 1;
+                    continue;
+                }
+                Conflict::Fail => {
+                    return Err(format!(
+                        "{} is already in hosts file",
+                        entry.names.join(" ")
+                    ))
+                }
+                Conflict::Overwrite => {
+                    for index in conflicts.iter().rev() {
+                        hosts_file.remove_names_at(*index, &entry.names);
+                    }
+                    summary.replaced += 1;
+                }
+            }
+        } else {
+            summary.added += 1;
+        }
+        let comment = metadata::with_managed_tag(entry.comment.as_deref().unwrap_or_default());
+        let index = hosts_file.put_entry(
+            None,
+            &entry.ip,
+            &entry.names,
+            Some(format!("# {}", comment)),
+        )?;
+        if !entry.enabled {
+            hosts_file.toggle_at(index);
+        }
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOSTS: &str =
+        "# hosts\n127.0.0.1 localhost\n10.0.0.1 a.test b.test # web [expires=x]\n#10.0.0.2 c.test\n";
+
+    #[test]
+    fn read_entries() {
+        let hf = ManagedHostsFile::from_string(HOSTS, "test");
+        let entries = entries(&hf);
+        assert_eq!(entries.len(), 3);
+        assert_eq!(
+            entries[1],
+            Entry {
+                ip: String::from("10.0.0.1"),
+                names: vec![String::from("a.test"), String::from("b.test")],
+                comment: Some(String::from("web [expires=x]")),
+                enabled: true,
+            }
+        );
+        assert!(!entries[2].enabled);
+        assert!(entries[1].matches("WEB"));
+        assert!(!entries[0].matches("web"));
+        assert_eq!(entries[0].clone().without_system_names(), None);
+        assert_eq!(
+            entries[1].clone().without_system_names().as_ref(),
+            Some(&entries[1])
+        );
+        let line = HostsFileLine::from_string("::1 localhost ip6-localhost app.test").unwrap();
+        assert_eq!(
+            Entry::from_line(&line, true)
+                .without_system_names()
+                .unwrap()
+                .names,
+            vec!["app.test"]
+        );
+
+        assert_eq!(matching(&hf, ".test", &MatchType::Partial).len(), 2);
+        let exact = matching(&hf, "c.test", &MatchType::Exact);
+        assert_eq!(exact.len(), 1);
+        assert!(!exact[0].enabled);
+        assert!(matching(&hf, "test", &MatchType::Exact).is_empty());
+    }
+
+    #[test]
+    fn round_trip() {
+        let hf = ManagedHostsFile::from_string(HOSTS, "test");
+        let entries = entries(&hf);
+        for format in &[Format::Json, Format::Yaml, Format::Toml, Format::Csv] {
+            let text = serialize(&entries, *format, false).unwrap();
+            assert_eq!(
+                deserialize(&text, *format).unwrap(),
+                entries,
+                "{:?}",
+                format
+            );
+        }
+        assert_eq!(Format::from_path("hosts.YML"), Some(Format::Yaml));
+        assert_eq!(Format::from_path("hosts"), None);
+    }
+
+    #[test]
+    fn import_strategies() {
+        let imported = deserialize(
+            r#"[
+                {"ip": "10.0.0.9", "names": ["a.test"]},
+                {"ip": "10.0.0.3", "names": ["d.test"], "comment": "new", "enabled": false},
+                {"ip": "10.0.0.2", "names": ["c.test"], "enabled": false}
+            ]"#,
+            Format::Json,
+        )
+        .unwrap();
+
+        let mut hf = ManagedHostsFile::from_string(HOSTS, "test");
+        assert!(import(&mut hf, &imported, Conflict::Fail).is_err());
+
+        let mut hf = ManagedHostsFile::from_string(HOSTS, "test");
+        let summary = import(&mut hf, &imported, Conflict::Skip).unwrap();
+        assert_eq!(
+            summary,
+            ImportSummary {
+                added: 1,
+                replaced: 0,
+                skipped: 2
+            }
+        );
+        assert!(hf
+            .contents()
+            .ends_with("#10.0.0.3 d.test # new [by=hostman]\n"));
+
+        let mut hf = ManagedHostsFile::from_string(HOSTS, "test");
+        let summary = import(&mut hf, &imported, Conflict::Overwrite).unwrap();
+        assert_eq!(summary.replaced, 1);
+        assert_eq!(
+            hf.contents(),
+            "# hosts\n127.0.0.1 localhost\n10.0.0.1 b.test # web [expires=x]\n#10.0.0.2 c.test\n10.0.0.9 a.test # [by=hostman]\n#10.0.0.3 d.test # new [by=hostman]\n"
+        );
+
+        let dual_stack = deserialize(
+            r#"[{"ip": "10.0.0.4", "names": ["e.test"]}, {"ip": "fd00::4", "names": ["e.test"]}]"#,
+            Format::Json,
+        )
+        .unwrap();
+        let mut hf = ManagedHostsFile::from_string(HOSTS, "test");
+        assert_eq!(
+            import(&mut hf, &dual_stack, Conflict::Fail).unwrap().added,
+            2
+        );
+        assert_eq!(
+            import(&mut hf, &dual_stack, Conflict::Fail)
+                .unwrap()
+                .skipped,
+            2
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use fs2::FileExt;
 use regex::Regex;
 
 pub fn exact_match(needle: &str, haystack: &str) -> bool {
@@ -30,3 +31,86 @@ pub fn write_hosts(file_name: &str, contents: &str) {
     std::process::exit(1);
   }
 }
+
+/// Advisory lock shared by all hostman invocations editing the same hosts file.
+/// The lock is released when the value is dropped.
+#[derive(Debug)]
+pub struct HostsLock {
+  file: std::fs::File,
+}
+
+impl HostsLock {
+  pub fn acquire(file_name: &str) -> HostsLock {
+    let lock_name = format!("{}.lock", file_name);
+    let file = std::fs::OpenOptions::new()
+      .create(true)
+      .truncate(false)
+      .write(true)
+      .open(&lock_name);
+    let file = match file {
+      Ok(file) => file,
+      Err(_) => {
+        eprintln!(
+          "Error: cannot open lock file: {} -- did you forget to use sudo?",
+          lock_name
+        );
+        std::process::exit(1);
+      }
+    };
+    if file.lock_exclusive().is_err() {
+      eprintln!("Error: cannot lock hosts file: {}", file_name);
+      std::process::exit(1);
+    }
+    HostsLock { file }
+  }
+}
+
+impl Drop for HostsLock {
+  fn drop(&mut self) {
+    let _ = FileExt::unlock(&self.file);
+  }
+}
+
+/// Modification time and content hash of a hosts file when it was read, used to
+/// detect edits made by other programs before saving.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Fingerprint {
+  modified: Option<std::time::SystemTime>,
+  hash: u64,
+}
+
+impl Fingerprint {
+  /// Reads the file, returning its contents and fingerprint.
+  pub fn read(file_name: &str) -> (String, Fingerprint) {
+    // Stat before reading so that a write in between shows up as a changed mtime.
+    let modified = modified_time(file_name);
+    let contents = read_hosts(file_name);
+    let hash = hash_contents(&contents);
+    (contents, Fingerprint { modified, hash })
+  }
+
+  /// Whether the file on disk no longer matches this fingerprint.
+  pub fn changed(&self, file_name: &str) -> bool {
+    let modified = modified_time(file_name);
+    if modified.is_some() && modified == self.modified {
+      return false;
+    }
+    match std::fs::read_to_string(file_name) {
+      Ok(contents) => hash_contents(&contents) != self.hash,
+      Err(_) => true,
+    }
+  }
+}
+
+fn modified_time(file_name: &str) -> Option<std::time::SystemTime> {
+  std::fs::metadata(file_name)
+    .and_then(|m| m.modified())
+    .ok()
+}
+
+fn hash_contents(contents: &str) -> u64 {
+  use std::hash::{Hash, Hasher};
+  let mut hasher = std::collections::hash_map::DefaultHasher::new();
+  contents.hash(&mut hasher);
+  hasher.finish()
+}
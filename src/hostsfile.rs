@@ -1,11 +1,40 @@
+use crate::diff::normalize;
 use crate::file_utils::*;
+use crate::metadata::{expires, get_tag, has_tags, text, with_text};
+use chrono::{DateTime, Utc};
 use hosts_parser::HostsFile;
 use hosts_parser::HostsFileLine;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::net::IpAddr;
+use std::ops::RangeInclusive;
 
 const SYSTEM_HOSTS_FILE: &str = "/etc/hosts";
 // const HOSTS_FILE: &str = "./hosts";
 
+#[derive(Debug, Clone, Copy)]
+pub enum SortOrder {
+    Ip,
+    Name,
+}
+
+impl std::str::FromStr for SortOrder {
+    type Err = String;
+    fn from_str(s: &str) -> Result<SortOrder, String> {
+        match s {
+            "ip" => Ok(SortOrder::Ip),
+            "name" => Ok(SortOrder::Name),
+            _ => Err(format!("unknown sort order: {}", s)),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct FormatOptions {
+    /// Sort entries within each blank-line separated section.
+    pub sort: Option<SortOrder>,
+}
+
 pub enum MatchType {
     Partial,
     Exact,
@@ -21,10 +50,45 @@ impl MatchType {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug)]
 pub struct ManagedHostsFile {
     lines: Vec<HostsFileLine>,
     file_name: String,
+    original: String,
+    /// The loaded lines as they are written, which is `original` with every
+    /// line in canonical form.
+    loaded: String,
+    /// Whether entries are written in aligned columns.
+    aligned: bool,
+    lock: Option<HostsLock>,
+    fingerprint: Option<Fingerprint>,
+}
+
+/// The hosts file was changed by someone else after it was loaded.
+#[derive(Debug)]
+pub struct ConflictError {
+    pub file_name: String,
+}
+
+impl fmt::Display for ConflictError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} was modified since it was loaded", self.file_name)
+    }
+}
+
+impl std::error::Error for ConflictError {}
+
+/// A problem with a line of a hosts file, numbered from 1.
+#[derive(Debug, PartialEq, Eq)]
+pub struct LineError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for LineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
 }
 
 impl ManagedHostsFile {
@@ -33,29 +97,82 @@ impl ManagedHostsFile {
     }
 
     pub fn from_file(file_name: &str) -> Result<ManagedHostsFile, std::io::Error> {
-        let contents = read_hosts(file_name);
-        Ok(ManagedHostsFile::from_string(&contents, file_name))
+        let (contents, fingerprint) = Fingerprint::read(file_name);
+        let mut hosts_file = ManagedHostsFile::from_string(&contents, file_name);
+        hosts_file.fingerprint = Some(fingerprint);
+        Ok(hosts_file)
     }
 
     pub fn from_string(contents: &str, file_name: &str) -> ManagedHostsFile {
-        let hf: HostsFile = contents.parse().unwrap();
-        ManagedHostsFile {
-            lines: hf.lines,
+        ManagedHostsFile::try_from_string(contents, file_name).unwrap()
+    }
+
+    pub fn try_from_string(contents: &str, file_name: &str) -> Result<ManagedHostsFile, LineError> {
+        let lines = contents
+            .lines()
+            .enumerate()
+            .map(|(i, l)| {
+                HostsFileLine::from_string(l).map_err(|_| LineError {
+                    line: i + 1,
+                    message: format!("cannot parse `{}`", l.trim()),
+                })
+            })
+            .collect::<Result<Vec<HostsFileLine>, LineError>>()?;
+        // Files that were formatted when loaded stay formatted when saved.
+        let aligned = contents == render(&lines, true) && contents != render(&lines, false);
+        Ok(ManagedHostsFile {
+            loaded: render(&lines, aligned),
+            lines,
             file_name: String::from(file_name),
-        }
+            original: String::from(contents),
+            aligned,
+            lock: None,
+            fingerprint: None,
+        })
+    }
+
+    pub fn file_name(&self) -> &str {
+        &self.file_name
+    }
+
+    /// The contents of the file as they were when it was loaded.
+    pub fn original(&self) -> &str {
+        &self.original
+    }
+
+    /// The contents of the file as they were when it was loaded, as hostman
+    /// would write them. Comparing with this ignores whitespace-only differences.
+    pub fn loaded(&self) -> &str {
+        &self.loaded
+    }
+
+    /// Replaces every line with the ones parsed from `contents`.
+    pub fn set_contents(&mut self, contents: &str) {
+        let hf: HostsFile = contents.parse().unwrap();
+        self.lines = hf.lines;
     }
 
     pub fn must_load() -> ManagedHostsFile {
         ManagedHostsFile::load().unwrap()
     }
 
+    /// Loads the system hosts file, holding the hosts lock until the value is dropped.
+    pub fn must_load_locked() -> ManagedHostsFile {
+        ManagedHostsFile::must_load_file_locked(SYSTEM_HOSTS_FILE)
+    }
+
+    /// Loads a hosts file, holding its lock until the value is dropped.
+    pub fn must_load_file_locked(file_name: &str) -> ManagedHostsFile {
+        let lock = HostsLock::acquire(file_name);
+        let mut hosts_file = ManagedHostsFile::from_file(file_name).unwrap();
+        hosts_file.lock = Some(lock);
+        hosts_file
+    }
+
     pub fn get_matches(&self, host: &str, exact: &MatchType) -> Vec<&HostsFileLine> {
         self.lines
             .iter()
-            .filter(|line| match exact {
-                MatchType::Exact => exact_match(host, format!("{}", line).as_str()),
-                MatchType::Partial => format!("{}", line).contains(host),
-            })
+            .filter(|line| matches(line, host, exact))
             .collect()
     }
 
@@ -85,6 +202,138 @@ impl ManagedHostsFile {
         self.lines.push(l);
     }
 
+    /// Appends names to every enabled line containing `host`, returning how many
+    /// lines changed.
+    pub fn add_aliases(&mut self, host: &str, names: &[&str]) -> usize {
+        let mut changed = 0;
+        for line in self.lines.iter_mut().filter(|l| has_name(l, host)) {
+            if let Some(new_line) = with_names(line, names) {
+                *line = new_line;
+                changed += 1;
+            }
+        }
+        changed
+    }
+
+    /// Removes `alias` from every enabled line that also contains `host`, as long
+    /// as the line keeps at least one name. Returns how many lines changed.
+    pub fn remove_alias(&mut self, host: &str, alias: &str) -> usize {
+        let mut changed = 0;
+        for line in self.lines.iter_mut().filter(|l| has_name(l, host)) {
+            let hosts = line.hosts();
+            let remaining: Vec<String> = hosts.iter().filter(|h| *h != alias).cloned().collect();
+            if remaining.len() == hosts.len() || remaining.is_empty() {
+                continue;
+            }
+            *line = line_from_parts(&line.ip().unwrap(), &remaining, line.comment());
+            changed += 1;
+        }
+        changed
+    }
+
+    /// Changes the ip of every enabled line containing `host`, keeping its position
+    /// and comment. With `split`, a host that shares its line with other names is
+    /// moved to a new line right below instead. Returns how many lines changed.
+    pub fn move_host(&mut self, host: &str, ip: &str, split: bool) -> usize {
+        let mut changed = 0;
+        let lines = std::mem::take(&mut self.lines);
+        for line in lines {
+            if !has_name(&line, host) || line.ip().as_deref() == Some(ip) {
+                self.lines.push(line);
+                continue;
+            }
+            changed += 1;
+            let hosts = line.hosts();
+            if split && hosts.len() > 1 {
+                let others: Vec<String> = hosts.iter().filter(|h| *h != host).cloned().collect();
+                let old_ip = line.ip().unwrap();
+                self.lines
+                    .push(line_from_parts(&old_ip, &others, line.comment()));
+                self.lines
+                    .push(line_from_parts(ip, &[String::from(host)], line.comment()));
+            } else {
+                self.lines.push(line_from_parts(ip, &hosts, line.comment()));
+            }
+        }
+        changed
+    }
+
+    /// Renames hosts on enabled and disabled lines, keeping ips and comments.
+    /// `rename` returns the new name for a host, or None to keep it. Returns how
+    /// many names were changed.
+    pub fn rename_hosts(&mut self, rename: &dyn Fn(&str) -> Option<String>) -> usize {
+        let mut changed = 0;
+        for line in self.lines.iter_mut() {
+            let entry = match entry(line) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            let mut renamed = 0;
+            let hosts: Vec<String> = entry
+                .hosts()
+                .iter()
+                .map(|h| match rename(h) {
+                    Some(new_name) => {
+                        renamed += 1;
+                        new_name
+                    }
+                    None => h.clone(),
+                })
+                .collect();
+            if renamed == 0 {
+                continue;
+            }
+            let new_line = line_from_parts(&entry.ip().unwrap(), &hosts, entry.comment());
+            *line = if line.has_host() {
+                new_line
+            } else {
+                HostsFileLine::from_comment(&format!("#{}", new_line))
+            };
+            changed += renamed;
+        }
+        changed
+    }
+
+    /// Returns the enabled lines that contain `host`.
+    pub fn host_lines(&self, host: &str) -> Vec<&HostsFileLine> {
+        self.lines.iter().filter(|l| has_name(l, host)).collect()
+    }
+
+    /// Sets the comment text of every enabled line containing `host`, keeping its
+    /// metadata tags. Returns how many lines changed.
+    pub fn set_comment(&mut self, host: &str, text: &str) -> usize {
+        let mut changed = 0;
+        for line in self.lines.iter_mut().filter(|l| has_name(l, host)) {
+            let comment = with_text(&line.comment().unwrap_or_default(), text);
+            let comment = if comment.is_empty() {
+                None
+            } else {
+                Some(comment)
+            };
+            if comment == line.comment() {
+                continue;
+            }
+            *line = line_from_parts(&line.ip().unwrap(), &line.hosts(), comment);
+            changed += 1;
+        }
+        changed
+    }
+
+    /// Appends names to the first enabled, untagged line with the given ip.
+    /// Returns false if there is no such line or it already has every name.
+    pub fn merge_names(&mut self, ip: &str, names: &[&str]) -> bool {
+        let position = self.lines.iter().position(|l| {
+            l.ip().as_deref() == Some(ip) && !l.comment().map(|c| has_tags(&c)).unwrap_or(false)
+        });
+        match position.and_then(|index| Some((index, with_names(&self.lines[index], names)?))) {
+            Some((index, new_line)) => {
+                self.lines[index] = new_line;
+                true
+            }
+            None => false,
+        }
+    }
+
     pub fn remove_host(&mut self, host: &str) {
         let index = self
             .lines
@@ -94,6 +343,39 @@ impl ManagedHostsFile {
         self.lines.remove(index);
     }
 
+    /// Removes every line (enabled or disabled) tagged with `[key=value]`.
+    pub fn remove_tagged(&mut self, key: &str, value: &str) -> usize {
+        let before = self.lines.len();
+        self.lines.retain(|l| match l.comment() {
+            Some(c) => get_tag(&c, key).as_deref() != Some(value),
+            _ => true,
+        });
+        before - self.lines.len()
+    }
+
+    /// Returns the lines whose `[expires=...]` tag is at or before `now`.
+    pub fn expired(&self, now: DateTime<Utc>) -> Vec<&HostsFileLine> {
+        self.lines.iter().filter(|l| is_expired(l, now)).collect()
+    }
+
+    /// Removes expired lines, enabled or disabled.
+    pub fn remove_expired(&mut self, now: DateTime<Utc>) -> usize {
+        let before = self.lines.len();
+        self.lines.retain(|l| !is_expired(l, now));
+        before - self.lines.len()
+    }
+
+    /// Comments out expired lines that are still enabled.
+    pub fn disable_expired(&mut self, now: DateTime<Utc>) -> usize {
+        let positions: Vec<usize> = (0..self.lines.len())
+            .filter(|i| self.lines[*i].has_host() && is_expired(&self.lines[*i], now))
+            .collect();
+        for index in &positions {
+            self.disable_at(*index);
+        }
+        positions.len()
+    }
+
     pub fn disable_host(&mut self, host: &str) {
         let position = self
             .lines
@@ -101,14 +383,18 @@ impl ManagedHostsFile {
             .position(|l: &HostsFileLine| l.hosts().iter().any(|h| h == host));
 
         if let Some(index) = position {
-            let comment = format!("#{}", self.lines[index]);
-            let new_line = HostsFileLine::from_comment(&comment);
-            self.lines[index] = new_line;
+            self.disable_at(index);
         } else {
             println!("Error, line not found for {}", host)
         }
     }
 
+    fn disable_at(&mut self, index: usize) {
+        let comment = format!("#{}", self.lines[index]);
+        let new_line = HostsFileLine::from_comment(&comment);
+        self.lines[index] = new_line;
+    }
+
     pub fn enable_host(&mut self, host: &str) {
         let position = self.lines.iter().position(|l| match l.comment() {
             Some(c) => c.contains(host),
@@ -124,11 +410,355 @@ impl ManagedHostsFile {
         }
     }
 
+    /// Returns the index of every enabled or disabled entry, with whether it is
+    /// currently enabled.
+    pub fn entries(&self) -> Vec<(usize, bool)> {
+        self.lines
+            .iter()
+            .enumerate()
+            .filter(|(_, l)| entry(l).is_some())
+            .map(|(i, l)| (i, l.has_host()))
+            .collect()
+    }
+
+    /// Returns the line at `index`.
+    pub fn line(&self, index: usize) -> &HostsFileLine {
+        &self.lines[index]
+    }
+
+    /// Returns the index of every enabled or disabled entry containing `host`.
+    pub fn find_entries(&self, host: &str) -> Vec<usize> {
+        self.entries()
+            .into_iter()
+            .filter(|(i, _)| entry(&self.lines[*i]).is_some_and(|e| has_name(&e, host)))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Returns the entry at `index` as an enabled line, whether or not it is
+    /// disabled in the file.
+    pub fn entry_at(&self, index: usize) -> Option<HostsFileLine> {
+        entry(&self.lines[index])
+    }
+
+    /// Replaces the entry at `index`, keeping it enabled or disabled, or appends
+    /// a new enabled entry when `index` is `None`. Returns the entry's index.
+    pub fn put_entry(
+        &mut self,
+        index: Option<usize>,
+        ip: &str,
+        names: &[String],
+        comment: Option<String>,
+    ) -> Result<usize, String> {
+        if address(ip).is_none() {
+            return Err(format!("invalid ip address: {}", ip));
+        }
+        if names.is_empty() {
+            return Err(String::from("at least one name is required"));
+        }
+        let line = line_from_parts(ip, names, comment);
+        match index {
+            Some(index) => {
+                let enabled = self.lines[index].has_host();
+                self.lines[index] = line;
+                if !enabled {
+                    self.disable_at(index);
+                }
+                Ok(index)
+            }
+            None => {
+                self.lines.push(line);
+                Ok(self.lines.len() - 1)
+            }
+        }
+    }
+
+    /// Removes the line at `index`.
+    pub fn remove_at(&mut self, index: usize) {
+        self.lines.remove(index);
+    }
+
+    /// Takes `names` off the entry at `index`, keeping it enabled or disabled,
+    /// and removes the line if no name is left.
+    pub fn remove_names_at(&mut self, index: usize, names: &[String]) {
+        let line = match entry(&self.lines[index]) {
+            Some(line) => line,
+            None => return,
+        };
+        let hosts = line
+            .hosts()
+            .into_iter()
+            .filter(|h| !names.contains(h))
+            .collect::<Vec<String>>();
+        if hosts.is_empty() {
+            self.remove_at(index);
+        } else {
+            let ip = line.ip().unwrap();
+            self.put_entry(Some(index), &ip, &hosts, line.comment())
+                .unwrap();
+        }
+    }
+
+    /// Disables the entry at `index` if it is enabled, or enables it if it is
+    /// disabled. Lines that are not entries are left untouched.
+    pub fn toggle_at(&mut self, index: usize) {
+        if self.lines[index].has_host() {
+            self.disable_at(index);
+        } else if let Some(enabled) = entry(&self.lines[index]) {
+            self.lines[index] = enabled;
+        }
+    }
+
+    /// Flips every enabled or disabled entry containing `host`, returning how
+    /// many lines changed.
+    pub fn toggle_host(&mut self, host: &str) -> usize {
+        let indices = self.find_entries(host);
+        for index in &indices {
+            self.toggle_at(*index);
+        }
+        indices.len()
+    }
+
+    /// Returns the lines of the file.
+    pub fn lines(&self) -> &[HostsFileLine] {
+        &self.lines
+    }
+
+    /// Returns the name and begin marker comment of every `# BEGIN <name>` ...
+    /// `# END <name>` section, in file order.
+    pub fn sections(&self) -> Vec<(String, String)> {
+        self.lines
+            .iter()
+            .filter(|l| !l.has_host())
+            .filter_map(|l| l.comment())
+            .filter_map(|c| {
+                let name = text(&c).strip_prefix("BEGIN ").map(String::from)?;
+                self.section(&name).map(|_| (name, c))
+            })
+            .collect()
+    }
+
+    /// Returns the lines of a section, markers included.
+    pub fn section(&self, name: &str) -> Option<RangeInclusive<usize>> {
+        let marker = |kind: &str, from: usize| {
+            self.lines[from..]
+                .iter()
+                .position(|l| {
+                    !l.has_host()
+                        && l.comment()
+                            .is_some_and(|c| text(&c) == format!("{} {}", kind, name))
+                })
+                .map(|i| i + from)
+        };
+        let begin = marker("BEGIN", 0)?;
+        let end = marker("END", begin)?;
+        Some(begin..=end)
+    }
+
+    /// Replaces the lines of a section, or appends the section to the file. The
+    /// begin marker carries `tags`.
+    pub fn put_section(&mut self, name: &str, tags: &str, lines: Vec<HostsFileLine>) {
+        let begin = HostsFileLine::from_comment(&with_text(tags, &format!("BEGIN {}", name)));
+        let end = HostsFileLine::from_comment(&format!("# END {}", name));
+        let section = std::iter::once(begin)
+            .chain(lines)
+            .chain(std::iter::once(end));
+        match self.section(name) {
+            Some(range) => {
+                self.lines.splice(range, section);
+            }
+            None => {
+                if self
+                    .lines
+                    .last()
+                    .is_some_and(|l| l.has_host() || l.has_comment())
+                {
+                    self.lines.push(HostsFileLine::from_empty());
+                }
+                self.lines.extend(section);
+            }
+        }
+    }
+
+    /// Removes a section and its markers, returning whether it existed. The
+    /// blank line `put_section` added before it goes too.
+    pub fn remove_section(&mut self, name: &str) -> bool {
+        match self.section(name) {
+            Some(range) => {
+                let start = *range.start();
+                self.lines.drain(range);
+                let is_blank = |l: &HostsFileLine| !l.has_host() && !l.has_comment();
+                if start > 0
+                    && is_blank(&self.lines[start - 1])
+                    && self.lines.get(start).map_or(true, is_blank)
+                {
+                    self.lines.remove(start - 1);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Checks enabled lines for invalid addresses and for names defined more than
+    /// once for the same address family. Scoped (`%zone`) addresses may repeat names.
+    pub fn validate(&self) -> Vec<LineError> {
+        let mut errors = vec![];
+        let mut seen: HashMap<(bool, String), usize> = HashMap::new();
+        for (i, l) in self.lines.iter().enumerate().filter(|(_, l)| l.has_host()) {
+            let ip = l.ip().unwrap();
+            let (address, zone) = match ip.find('%') {
+                Some(index) => (&ip[..index], true),
+                None => (ip.as_str(), false),
+            };
+            let address = match address.parse::<IpAddr>() {
+                Ok(address) => address,
+                Err(_) => {
+                    errors.push(LineError {
+                        line: i + 1,
+                        message: format!("invalid address {}", ip),
+                    });
+                    continue;
+                }
+            };
+            if zone {
+                continue;
+            }
+            for name in l.hosts() {
+                let key = (address.is_ipv4(), name.to_lowercase());
+                match seen.get(&key) {
+                    Some(first) => errors.push(LineError {
+                        line: i + 1,
+                        message: format!("{} is already defined on line {}", name, first),
+                    }),
+                    None => {
+                        seen.insert(key, i + 1);
+                    }
+                }
+            }
+        }
+        errors
+    }
+
+    /// Removes repeated entries and merges enabled entries sharing an address into
+    /// the first line with that address, with at most `max_aliases` aliases per
+    /// line. Entries with metadata tags are only merged with exact duplicates.
+    pub fn dedupe(&mut self, max_aliases: Option<usize>) {
+        let mut seen = HashSet::new();
+        self.lines
+            .retain(|l| !l.has_host() || seen.insert(normalize(&format!("{}", l))));
+
+        let mut groups: Vec<(String, Vec<usize>)> = vec![];
+        for (i, l) in self.lines.iter().enumerate() {
+            if !l.has_host() || l.comment().map(|c| has_tags(&c)).unwrap_or(false) {
+                continue;
+            }
+            let ip = l.ip().unwrap();
+            match groups.iter_mut().find(|(group_ip, _)| *group_ip == ip) {
+                Some((_, indexes)) => indexes.push(i),
+                None => groups.push((ip, vec![i])),
+            }
+        }
+
+        let per_line = max_aliases.map(|n| n + 1).unwrap_or(usize::MAX);
+        let mut replacements: HashMap<usize, Vec<HostsFileLine>> = HashMap::new();
+        for (ip, indexes) in groups {
+            let mut names: Vec<String> = vec![];
+            let mut comments: Vec<String> = vec![];
+            let mut name_count = 0;
+            for l in indexes.iter().map(|i| &self.lines[*i]) {
+                for name in l.hosts() {
+                    name_count += 1;
+                    if !names.contains(&name) {
+                        names.push(name);
+                    }
+                }
+                if let Some(comment) = l
+                    .comment()
+                    .filter(|c| !c.trim_matches('#').trim().is_empty())
+                {
+                    comments.push(comment);
+                }
+            }
+            if indexes.len() == 1 && names.len() == name_count && names.len() <= per_line {
+                continue;
+            }
+            let mut texts: Vec<String> = vec![];
+            for comment in &comments {
+                let text = String::from(comment.trim_start_matches('#').trim());
+                if !text.is_empty() && !texts.contains(&text) {
+                    texts.push(text);
+                }
+            }
+            let comment = match texts.len() {
+                0 => String::new(),
+                1 => format!(" {}", comments[0]),
+                _ => format!(" # {}", texts.join("; ")),
+            };
+            let merged = names
+                .chunks(per_line)
+                .map(|chunk| {
+                    HostsFileLine::from_string(&format!("{} {}{}", ip, chunk.join(" "), comment))
+                        .unwrap()
+                })
+                .collect();
+            replacements.insert(indexes[0], merged);
+            for i in &indexes[1..] {
+                replacements.insert(*i, vec![]);
+            }
+        }
+
+        let lines = std::mem::take(&mut self.lines);
+        for (i, line) in lines.into_iter().enumerate() {
+            match replacements.remove(&i) {
+                Some(merged) => self.lines.extend(merged),
+                None => self.lines.push(line),
+            }
+        }
+    }
+
+    /// Puts the file in canonical form: entries in aligned columns, optionally
+    /// sorted within each section. Comment lines move with the entry below them.
+    pub fn format(&mut self, options: &FormatOptions) {
+        if let Some(order) = &options.sort {
+            self.sort(order);
+        }
+        self.aligned = true;
+    }
+
+    /// Whether the lines are still as they were loaded. Differences in
+    /// whitespace between the file and its rendering don't count.
+    pub fn is_unchanged(&self) -> bool {
+        self.loaded == self.contents()
+    }
+
+    fn sort(&mut self, order: &SortOrder) {
+        let mut sorted = vec![];
+        let mut blocks: Vec<Vec<HostsFileLine>> = vec![];
+        let mut pending: Vec<HostsFileLine> = vec![];
+        for line in std::mem::take(&mut self.lines) {
+            if !line.has_host() && !line.has_comment() {
+                sort_blocks(&mut blocks, order);
+                sorted.extend(blocks.drain(..).flatten());
+                sorted.append(&mut pending);
+                sorted.push(line);
+            } else if entry(&line).is_some() {
+                pending.push(line);
+                blocks.push(std::mem::take(&mut pending));
+            } else {
+                pending.push(line);
+            }
+        }
+        sort_blocks(&mut blocks, order);
+        sorted.extend(blocks.drain(..).flatten());
+        sorted.append(&mut pending);
+        self.lines = sorted;
+    }
+
     pub fn without_comments(&self) -> Vec<&HostsFileLine> {
         self.lines
             .iter()
             .filter(|l| l.has_host())
-            .map(|l| l)
             .collect::<Vec<&HostsFileLine>>()
     }
 
@@ -136,17 +766,136 @@ impl ManagedHostsFile {
         format!("{}", self)
     }
 
-    pub fn save(&self) {
+    /// Writes the file, refusing to overwrite changes made since it was loaded.
+    pub fn save(&self) -> Result<(), ConflictError> {
+        if let Some(fingerprint) = &self.fingerprint {
+            if fingerprint.changed(&self.file_name) {
+                return Err(ConflictError {
+                    file_name: self.file_name.clone(),
+                });
+            }
+        }
         let file_content = self.contents();
         write_hosts(&self.file_name, &file_content);
+        Ok(())
     }
 }
 
-impl<'a> fmt::Display for ManagedHostsFile {
+fn is_expired(line: &HostsFileLine, now: DateTime<Utc>) -> bool {
+    match line.comment().and_then(|c| expires(&c)) {
+        Some(t) => t <= now,
+        None => false,
+    }
+}
+
+/// Whether the line matches `host` the way `check` looks for it.
+pub fn matches(line: &HostsFileLine, host: &str, exact: &MatchType) -> bool {
+    match exact {
+        MatchType::Exact => exact_match(host, format!("{}", line).as_str()),
+        MatchType::Partial => format!("{}", line).contains(host),
+    }
+}
+
+fn has_name(line: &HostsFileLine, host: &str) -> bool {
+    line.hosts().iter().any(|h| h == host)
+}
+
+/// Returns the line with the names it doesn't have yet appended, if any.
+fn with_names(line: &HostsFileLine, names: &[&str]) -> Option<HostsFileLine> {
+    let mut hosts = line.hosts();
+    let before = hosts.len();
+    for name in names {
+        if !hosts.iter().any(|h| h == name) {
+            hosts.push(String::from(*name));
+        }
+    }
+    if hosts.len() == before {
+        return None;
+    }
+    Some(line_from_parts(&line.ip().unwrap(), &hosts, line.comment()))
+}
+
+fn line_from_parts(ip: &str, hosts: &[String], comment: Option<String>) -> HostsFileLine {
+    let text = match comment {
+        Some(comment) => format!("{} {} {}", ip, hosts.join(" "), comment),
+        None => format!("{} {}", ip, hosts.join(" ")),
+    };
+    HostsFileLine::from_string(&text).unwrap()
+}
+
+/// Returns the entry a line holds, parsing commented out entries too.
+fn entry(line: &HostsFileLine) -> Option<HostsFileLine> {
+    if line.has_host() {
+        return HostsFileLine::from_string(&format!("{}", line)).ok();
+    }
+    let comment = line.comment()?;
+    let disabled = HostsFileLine::from_string(comment.trim_start_matches('#')).ok()?;
+    address(&disabled.ip()?)?;
+    Some(disabled)
+}
+
+/// Parses an address, ignoring any `%zone` suffix.
+pub fn address(ip: &str) -> Option<IpAddr> {
+    ip.split('%').next()?.parse().ok()
+}
+
+fn sort_blocks(blocks: &mut [Vec<HostsFileLine>], order: &SortOrder) {
+    blocks.sort_by_cached_key(|block| {
+        let entry = block.last().and_then(entry);
+        let name = entry
+            .as_ref()
+            .and_then(|e| e.hosts().first().map(|h| h.to_lowercase()))
+            .unwrap_or_default();
+        let ip = match order {
+            SortOrder::Ip => match entry.and_then(|e| e.ip()).and_then(|ip| address(&ip)) {
+                Some(IpAddr::V4(a)) => (0, u128::from(u32::from(a))),
+                Some(IpAddr::V6(a)) => (1, u128::from(a)),
+                None => (2, 0),
+            },
+            SortOrder::Name => (0, 0),
+        };
+        (ip, name)
+    });
+}
+
+fn render(lines: &[HostsFileLine], aligned: bool) -> String {
+    let rendered: Vec<String> = if aligned {
+        align(lines)
+    } else {
+        lines.iter().map(|l| format!("{}", l)).collect()
+    };
+    format!("{}\n", rendered.join("\n"))
+}
+
+fn align(lines: &[HostsFileLine]) -> Vec<String> {
+    let entries = lines.iter().filter(|l| l.has_host());
+    let width = |column: &dyn Fn(&HostsFileLine) -> usize| entries.clone().map(column).max();
+    let ip_width = width(&|l| l.ip().unwrap().len()).unwrap_or(0);
+    let name_width = width(&|l| l.hosts()[0].len()).unwrap_or(0);
+    let alias_width = width(&|l| l.hosts()[1..].join(" ").len()).unwrap_or(0);
+    lines
+        .iter()
+        .map(|l| {
+            if !l.has_host() {
+                return format!("{}", l);
+            }
+            let hosts = l.hosts();
+            let mut columns = vec![
+                format!("{:<w$}", l.ip().unwrap(), w = ip_width),
+                format!("{:<w$}", hosts[0], w = name_width),
+            ];
+            if alias_width > 0 {
+                columns.push(format!("{:<w$}", hosts[1..].join(" "), w = alias_width));
+            }
+            columns.extend(l.comment());
+            String::from(columns.join(" ").trim_end())
+        })
+        .collect()
+}
+
+impl fmt::Display for ManagedHostsFile {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let hf = &self.lines;
-        let lines: Vec<String> = hf.iter().map(|l| format!("{}", l)).collect();
-        writeln!(f, "{}", lines.join("\n"))
+        write!(f, "{}", render(&self.lines, self.aligned))
     }
 }
 
@@ -163,6 +912,100 @@ mod tests {
         assert_eq!(hf.contents(), expected);
     }
 
+    #[test]
+    fn try_from_string() {
+        let contents = "# hosts file\n127.0.0.1 localhost\n127.0.0.2\n";
+        let err = ManagedHostsFile::try_from_string(contents, "test").unwrap_err();
+        assert_eq!(err.line, 3);
+        assert!(ManagedHostsFile::try_from_string("127.0.0.1 localhost\n", "test").is_ok());
+    }
+
+    #[test]
+    fn validate() {
+        let contents = "127.0.0.1 localhost\n::1 localhost\nfe80::1%lo0 localhost\n127.0.0.300 bad.test\n10.0.0.1 a.test A.test\n#10.0.0.2 a.test\n10.0.0.3 b.test a.test";
+        let hf = ManagedHostsFile::from_string(contents, "test");
+        let errors = hf
+            .validate()
+            .iter()
+            .map(|e| format!("{}", e))
+            .collect::<Vec<String>>();
+        assert_eq!(
+            errors,
+            [
+                "line 4: invalid address 127.0.0.300",
+                "line 5: A.test is already defined on line 5",
+                "line 7: a.test is already defined on line 5",
+            ]
+        );
+    }
+
+    #[test]
+    fn format() {
+        let contents = "# hosts file\n127.0.0.1  localhost\n10.0.0.10 b.test   alias.test # comment\n\n# v6\n::1 localhost\n# second\n10.0.0.9 a.test\n#10.0.0.2 disabled.test\n";
+        let mut hf = ManagedHostsFile::from_string(contents, "test");
+        hf.format(&FormatOptions { sort: None });
+        assert_eq!(
+            hf.contents(),
+            "# hosts file\n127.0.0.1 localhost\n10.0.0.10 b.test    alias.test # comment\n\n# v6\n::1       localhost\n# second\n10.0.0.9  a.test\n#10.0.0.2 disabled.test\n"
+        );
+
+        hf.format(&FormatOptions {
+            sort: Some(SortOrder::Ip),
+        });
+        assert_eq!(
+            hf.contents(),
+            "10.0.0.10 b.test    alias.test # comment\n# hosts file\n127.0.0.1 localhost\n\n#10.0.0.2 disabled.test\n# second\n10.0.0.9  a.test\n# v6\n::1       localhost\n"
+        );
+
+        hf.format(&FormatOptions {
+            sort: Some(SortOrder::Name),
+        });
+        assert_eq!(
+            hf.contents(),
+            "10.0.0.10 b.test    alias.test # comment\n# hosts file\n127.0.0.1 localhost\n\n# second\n10.0.0.9  a.test\n#10.0.0.2 disabled.test\n# v6\n::1       localhost\n"
+        );
+    }
+
+    #[test]
+    fn dedupe() {
+        let contents = "127.0.0.1 localhost\n10.0.0.1 a.test # Added by hostman\n127.0.0.1 app.test # Added by hostman\n10.0.0.1 a.test # Added by hostman\n10.0.0.1 b.test a.test # api\n#10.0.0.1 c.test\n10.0.0.1 d.test # Added by hostman [run=1]\n10.0.0.1 d.test # Added by hostman [run=1]\n";
+        let mut hf = ManagedHostsFile::from_string(contents, "test");
+        hf.dedupe(None);
+        assert_eq!(
+            hf.contents(),
+            "127.0.0.1 localhost app.test # Added by hostman\n10.0.0.1 a.test b.test # Added by hostman; api\n#10.0.0.1 c.test\n10.0.0.1 d.test # Added by hostman [run=1]\n"
+        );
+
+        let contents = "10.0.0.1 a.test b.test c.test\n10.0.0.1 d.test e.test\n10.0.0.2 f.test\n";
+        let mut hf = ManagedHostsFile::from_string(contents, "test");
+        hf.dedupe(Some(1));
+        assert_eq!(
+            hf.contents(),
+            "10.0.0.1 a.test b.test\n10.0.0.1 c.test d.test\n10.0.0.1 e.test\n10.0.0.2 f.test\n"
+        );
+
+        let contents = "10.0.0.1 a.test #\n10.0.0.1 b.test # api\n";
+        let mut hf = ManagedHostsFile::from_string(contents, "test");
+        hf.dedupe(None);
+        assert_eq!(hf.contents(), "10.0.0.1 a.test b.test # api\n");
+    }
+
+    #[test]
+    fn stays_formatted() {
+        let contents = "127.0.0.1 localhost\n10.0.0.1  a.test\n";
+        let mut hf = ManagedHostsFile::from_string(contents, "test");
+        assert!(hf.is_unchanged());
+        hf.add_line("10.0.0.100 long-name.test");
+        assert_eq!(
+            hf.contents(),
+            "127.0.0.1  localhost\n10.0.0.1   a.test\n10.0.0.100 long-name.test\n"
+        );
+
+        let hf = ManagedHostsFile::from_string("127.0.0.1\tlocalhost\n", "test");
+        assert!(hf.is_unchanged());
+        assert_ne!(hf.contents(), hf.original());
+    }
+
     #[test]
     fn get_matches() {
         // let contents = "# hosts file\n127.0.0.1  localhost\n127.0.0.2 test1.test test2.test\n# 127.0.0.1 localhost \n# 127.0.0.2 test3.test";
@@ -274,6 +1117,202 @@ mod tests {
         assert!(hf.contents() == before);
     }
 
+    #[test]
+    fn remove_tagged() {
+        let contents = "# hosts file\n127.0.0.1 localhost\n127.0.0.2 a.test # Added by hostman [run=1]\n#127.0.0.3 b.test # Added by hostman [run=1]\n127.0.0.4 c.test # Added by hostman [run=2]";
+        let mut hf = ManagedHostsFile::from_string(contents, "test");
+
+        assert_eq!(hf.remove_tagged("run", "1"), 2);
+        assert!(!hf.has_host("a.test"));
+        assert!(!hf.has_disabled_host("b.test"));
+        assert!(hf.has_host("c.test"));
+        assert_eq!(hf.remove_tagged("run", "1"), 0);
+    }
+
+    #[test]
+    fn expired() {
+        let contents = "# hosts file\n127.0.0.1 localhost\n127.0.0.2 a.test # [expires=2026-10-19T10:00:00Z]\n#127.0.0.3 b.test # [expires=2026-10-19T10:00:00Z]\n127.0.0.4 c.test # [expires=2026-10-19T12:00:00Z]";
+        let now = DateTime::parse_from_rfc3339("2026-10-19T11:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        let hf = ManagedHostsFile::from_string(contents, "test");
+        assert_eq!(hf.expired(now).len(), 2);
+
+        let mut hf = ManagedHostsFile::from_string(contents, "test");
+        assert_eq!(hf.remove_expired(now), 2);
+        assert!(!hf.has_host("a.test"));
+        assert!(!hf.has_disabled_host("b.test"));
+        assert!(hf.has_host("c.test"));
+
+        let mut hf = ManagedHostsFile::from_string(contents, "test");
+        assert_eq!(hf.disable_expired(now), 1);
+        assert!(hf.has_disabled_host("a.test"));
+        assert!(hf.has_disabled_host("b.test"));
+        assert!(hf.has_host("c.test"));
+        assert_eq!(hf.disable_expired(now), 0);
+    }
+
+    #[test]
+    fn save_detects_conflicts() {
+        let file_name = std::env::temp_dir()
+            .join(format!("hostman-conflict-{}", std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+        std::fs::write(&file_name, "127.0.0.1 localhost\n").unwrap();
+
+        let mut hf = ManagedHostsFile::from_file(&file_name).unwrap();
+        hf.add_line("127.0.0.2 a.test");
+        assert!(hf.save().is_ok());
+
+        let mut hf = ManagedHostsFile::from_file(&file_name).unwrap();
+        std::fs::write(&file_name, "127.0.0.1 localhost\n127.0.0.3 hand.test\n").unwrap();
+        hf.add_line("127.0.0.4 b.test");
+        assert!(hf.save().is_err());
+        assert!(read_hosts(&file_name).contains("hand.test"));
+
+        std::fs::remove_file(&file_name).unwrap();
+        std::fs::remove_file(format!("{}.bak", file_name)).unwrap();
+    }
+
+    #[test]
+    fn aliases() {
+        let contents =
+            "127.0.0.1 localhost\n::1 localhost\n10.0.0.1 app.test # my app\n#10.0.0.2 app.test\n";
+        let mut hf = ManagedHostsFile::from_string(contents, "test");
+
+        assert_eq!(hf.add_aliases("app.test", &["api.test", "app.test"]), 1);
+        assert_eq!(hf.add_aliases("app.test", &["api.test"]), 0);
+        assert_eq!(hf.add_aliases("localhost", &["me"]), 2);
+        assert_eq!(
+            hf.contents(),
+            "127.0.0.1 localhost me\n::1 localhost me\n10.0.0.1 app.test api.test # my app\n#10.0.0.2 app.test\n"
+        );
+
+        assert_eq!(hf.remove_alias("app.test", "api.test"), 1);
+        assert_eq!(hf.remove_alias("app.test", "app.test"), 0);
+        assert_eq!(hf.remove_alias("me", "localhost"), 2);
+        assert_eq!(
+            hf.contents(),
+            "127.0.0.1 me\n::1 me\n10.0.0.1 app.test # my app\n#10.0.0.2 app.test\n"
+        );
+    }
+
+    #[test]
+    fn move_host() {
+        let contents = "127.0.0.1 localhost\n10.0.0.1 app.test api.test # my app\n#10.0.0.1 app.test\n10.0.0.2 other.test\n";
+        let mut hf = ManagedHostsFile::from_string(contents, "test");
+        assert_eq!(hf.move_host("app.test", "10.0.0.5", false), 1);
+        assert_eq!(hf.move_host("app.test", "10.0.0.5", false), 0);
+        assert_eq!(
+            hf.contents(),
+            "127.0.0.1 localhost\n10.0.0.5 app.test api.test # my app\n#10.0.0.1 app.test\n10.0.0.2 other.test\n"
+        );
+
+        let mut hf = ManagedHostsFile::from_string(contents, "test");
+        assert_eq!(hf.move_host("api.test", "10.0.0.6", true), 1);
+        assert_eq!(hf.move_host("other.test", "10.0.0.7", true), 1);
+        assert_eq!(
+            hf.contents(),
+            "127.0.0.1 localhost\n10.0.0.1 app.test # my app\n10.0.0.6 api.test # my app\n#10.0.0.1 app.test\n10.0.0.7 other.test\n"
+        );
+    }
+
+    #[test]
+    fn rename_hosts() {
+        let contents = "127.0.0.1 localhost\n10.0.0.1 app.dev api.dev # my app\n#10.0.0.2 old.dev # Added by hostman\n10.0.0.3 dev.example.com\n";
+        let mut hf = ManagedHostsFile::from_string(contents, "test");
+        assert_eq!(
+            hf.rename_hosts(&|h| h.strip_suffix(".dev").map(|n| format!("{}.test", n))),
+            3
+        );
+        assert_eq!(
+            hf.contents(),
+            "127.0.0.1 localhost\n10.0.0.1 app.test api.test # my app\n#10.0.0.2 old.test # Added by hostman\n10.0.0.3 dev.example.com\n"
+        );
+        assert_eq!(
+            hf.rename_hosts(&|h| if h == "localhost" {
+                Some(String::from("me"))
+            } else {
+                None
+            }),
+            1
+        );
+        assert!(hf.has_host("me"));
+    }
+
+    #[test]
+    fn toggle() {
+        let contents = "# hosts\n127.0.0.1 localhost\n10.0.0.1 a.test b.test # web\n#10.0.0.2 a.test\n# not a.test\n";
+        let mut hf = ManagedHostsFile::from_string(contents, "test");
+        assert_eq!(hf.entries(), vec![(1, true), (2, true), (3, false)]);
+        assert_eq!(hf.toggle_host("a.test"), 2);
+        assert_eq!(
+            hf.contents(),
+            "# hosts\n127.0.0.1 localhost\n#10.0.0.1 a.test b.test # web\n10.0.0.2 a.test\n# not a.test\n"
+        );
+        hf.toggle_at(2);
+        hf.toggle_at(4);
+        assert_eq!(
+            hf.contents(),
+            "# hosts\n127.0.0.1 localhost\n10.0.0.1 a.test b.test # web\n10.0.0.2 a.test\n# not a.test\n"
+        );
+        assert_eq!(hf.toggle_host("missing.test"), 0);
+    }
+
+    #[test]
+    fn put_entry() {
+        let contents = "127.0.0.1 localhost\n#10.0.0.1 a.test # old\n";
+        let mut hf = ManagedHostsFile::from_string(contents, "test");
+        let names = vec![String::from("b.test"), String::from("c.test")];
+        assert_eq!(
+            hf.put_entry(Some(1), "10.0.0.2", &names, Some(String::from("# new"))),
+            Ok(1)
+        );
+        assert_eq!(hf.put_entry(None, "10.0.0.3", &names[..1], None), Ok(2));
+        assert!(hf.put_entry(None, "nope", &names, None).is_err());
+        assert!(hf.put_entry(None, "10.0.0.4", &[], None).is_err());
+        assert_eq!(
+            hf.contents(),
+            "127.0.0.1 localhost\n#10.0.0.2 b.test c.test # new\n10.0.0.3 b.test\n"
+        );
+        assert_eq!(hf.entry_at(1).unwrap().ip(), Some(String::from("10.0.0.2")));
+        hf.remove_at(0);
+        assert_eq!(hf.entries(), vec![(0, false), (1, true)]);
+    }
+
+    #[test]
+    fn set_comment() {
+        let contents = "127.0.0.1 localhost\n10.0.0.1 app.test # Added by hostman [expires=x]\n10.0.0.2 app.test\n#10.0.0.3 app.test # old\n";
+        let mut hf = ManagedHostsFile::from_string(contents, "test");
+        assert_eq!(hf.host_lines("app.test").len(), 2);
+        assert_eq!(hf.set_comment("app.test", "my app"), 2);
+        assert_eq!(hf.set_comment("app.test", "my app"), 0);
+        assert_eq!(
+            hf.contents(),
+            "127.0.0.1 localhost\n10.0.0.1 app.test # my app [expires=x]\n10.0.0.2 app.test # my app\n#10.0.0.3 app.test # old\n"
+        );
+        assert_eq!(hf.set_comment("app.test", ""), 2);
+        assert_eq!(
+            hf.contents(),
+            "127.0.0.1 localhost\n10.0.0.1 app.test # [expires=x]\n10.0.0.2 app.test\n#10.0.0.3 app.test # old\n"
+        );
+    }
+
+    #[test]
+    fn merge_names() {
+        let contents = "10.0.0.1 a.test # [run=1]\n10.0.0.1 b.test # mine\n";
+        let mut hf = ManagedHostsFile::from_string(contents, "test");
+
+        assert!(hf.merge_names("10.0.0.1", &["c.test", "b.test"]));
+        assert!(!hf.merge_names("10.0.0.2", &["d.test"]));
+        assert!(!hf.merge_names("10.0.0.1", &["c.test"]));
+        assert_eq!(
+            hf.contents(),
+            "10.0.0.1 a.test # [run=1]\n10.0.0.1 b.test c.test # mine\n"
+        );
+    }
+
     #[test]
     fn disable_host() {
         let contents = "# hosts file\n127.0.0.1 localhost\n127.0.0.2 test1.test test2.test\n# 127.0.0.1 localhost \n# 127.0.0.2 test3.test";
@@ -312,4 +1351,32 @@ mod tests {
             .join("\n");
         assert_eq!(woc, "127.0.0.1 localhost\n127.0.0.2 test1.test test2.test");
     }
+
+    #[test]
+    fn sections() {
+        let contents = "127.0.0.1 localhost\n# BEGIN ads [source=a]\n0.0.0.0 ads.test\n# END ads\n";
+        let mut hf = ManagedHostsFile::from_string(contents, "test");
+        assert_eq!(hf.section("ads"), Some(1..=3));
+        assert_eq!(
+            hf.sections(),
+            vec![(String::from("ads"), String::from("# BEGIN ads [source=a]"))]
+        );
+
+        let line = |l: &str| HostsFileLine::from_string(l).unwrap();
+        hf.put_section(
+            "ads",
+            "[source=b]",
+            vec![line("0.0.0.0 a.test"), line("0.0.0.0 b.test")],
+        );
+        hf.put_section("more", "", vec![]);
+        assert_eq!(
+            hf.contents(),
+            "127.0.0.1 localhost\n# BEGIN ads [source=b]\n0.0.0.0 a.test\n0.0.0.0 b.test\n# END ads\n\n# BEGIN more\n# END more\n"
+        );
+        assert!(hf.remove_section("ads"));
+        assert!(!hf.remove_section("ads"));
+        assert_eq!(hf.section("more"), Some(2..=3));
+        assert!(hf.remove_section("more"));
+        assert_eq!(hf.contents(), "127.0.0.1 localhost\n");
+    }
 }
@@ -0,0 +1,224 @@
+use crate::diff::{normalize, Hunk};
+use crate::hostsfile::address;
+use chrono::{DateTime, Utc};
+use hosts_parser::HostsFileLine;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum Action {
+    Change,
+    Undo { of: u64 },
+    Redo { of: u64 },
+}
+
+/// One saved change to the hosts file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Record {
+    pub id: u64,
+    pub timestamp: DateTime<Utc>,
+    pub user: String,
+    pub command: String,
+    #[serde(flatten)]
+    pub action: Action,
+    pub hunks: Vec<Hunk>,
+}
+
+impl Record {
+    /// Builds the next record for a change made by the current process.
+    pub fn new(previous: &[Record], action: Action, hunks: Vec<Hunk>) -> Record {
+        Record {
+            id: previous.last().map(|r| r.id + 1).unwrap_or(1),
+            timestamp: Utc::now(),
+            user: current_user(),
+            command: current_command(),
+            action,
+            hunks,
+        }
+    }
+}
+
+pub fn journal_path(file_name: &str) -> String {
+    format!("{}.journal", file_name)
+}
+
+/// Reads all records, skipping lines that cannot be parsed.
+pub fn load(file_name: &str) -> Vec<Record> {
+    match std::fs::read_to_string(journal_path(file_name)) {
+        Ok(contents) => contents
+            .lines()
+            .filter_map(|l| serde_json::from_str(l).ok())
+            .collect(),
+        Err(_) => vec![],
+    }
+}
+
+pub fn append(file_name: &str, record: &Record) -> Result<(), std::io::Error> {
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(journal_path(file_name))?;
+    writeln!(file, "{}", serde_json::to_string(record)?)
+}
+
+/// Returns the changes that can be undone and the ones that can be redone, most
+/// recent last.
+pub fn stacks(records: &[Record]) -> (Vec<&Record>, Vec<&Record>) {
+    let mut applied: Vec<&Record> = vec![];
+    let mut undone: Vec<&Record> = vec![];
+    for record in records {
+        match record.action {
+            Action::Change => {
+                applied.push(record);
+                undone.clear();
+            }
+            Action::Undo { of } => {
+                if let Some(index) = applied.iter().rposition(|r| r.id == of) {
+                    undone.push(applied.remove(index));
+                }
+            }
+            Action::Redo { of } => {
+                if let Some(index) = undone.iter().rposition(|r| r.id == of) {
+                    applied.push(undone.remove(index));
+                }
+            }
+        }
+    }
+    (applied, undone)
+}
+
+/// Whether any entry added or removed by the record, enabled or disabled, has
+/// the name `host`.
+pub fn touches(record: &Record, host: &str) -> bool {
+    record
+        .hunks
+        .iter()
+        .flat_map(|h| h.removed.iter().chain(h.added.iter()))
+        .any(|l| names(l).iter().any(|n| n == host))
+}
+
+/// Returns the names of an enabled or disabled entry line.
+fn names(line: &str) -> Vec<String> {
+    let entry = match HostsFileLine::from_string(line.trim_start().trim_start_matches('#')) {
+        Ok(entry) => entry,
+        Err(_) => return vec![],
+    };
+    match entry.ip().and_then(|ip| address(&ip)) {
+        Some(_) => entry.hosts(),
+        None => vec![],
+    }
+}
+
+/// Returns the most recent record that wrote `line`.
+pub fn last_added<'a>(records: &'a [Record], line: &str) -> Option<&'a Record> {
+    let line = normalize(line);
+    records.iter().rev().find(|r| {
+        r.hunks
+            .iter()
+            .flat_map(|h| h.added.iter())
+            .any(|l| normalize(l) == line)
+    })
+}
+
+fn current_user() -> String {
+    ["SUDO_USER", "USER", "USERNAME"]
+        .iter()
+        .find_map(|v| std::env::var(v).ok())
+        .unwrap_or_else(|| String::from("unknown"))
+}
+
+fn current_command() -> String {
+    let mut args: Vec<String> = std::env::args().collect();
+    if !args.is_empty() {
+        args[0] = String::from("hostman");
+    }
+    args.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(id: u64, action: Action) -> Record {
+        Record {
+            id,
+            timestamp: Utc::now(),
+            user: String::from("test"),
+            command: String::from("hostman add"),
+            action,
+            hunks: vec![],
+        }
+    }
+
+    fn change(id: u64, removed: &[&str], added: &[&str]) -> Record {
+        let mut r = record(id, Action::Change);
+        r.hunks = crate::diff::diff_lines(removed, added);
+        r
+    }
+
+    #[test]
+    fn undo_redo_stacks() {
+        let records = vec![
+            record(1, Action::Change),
+            record(2, Action::Change),
+            record(3, Action::Undo { of: 2 }),
+        ];
+        let (applied, undone) = stacks(&records);
+        assert_eq!(applied.iter().map(|r| r.id).collect::<Vec<u64>>(), [1]);
+        assert_eq!(undone.iter().map(|r| r.id).collect::<Vec<u64>>(), [2]);
+
+        let mut records = records;
+        records.push(record(4, Action::Redo { of: 2 }));
+        let (applied, undone) = stacks(&records);
+        assert_eq!(applied.iter().map(|r| r.id).collect::<Vec<u64>>(), [1, 2]);
+        assert!(undone.is_empty());
+
+        records.push(record(5, Action::Undo { of: 2 }));
+        records.push(record(6, Action::Change));
+        let (applied, undone) = stacks(&records);
+        assert_eq!(applied.iter().map(|r| r.id).collect::<Vec<u64>>(), [1, 6]);
+        assert!(undone.is_empty());
+    }
+
+    #[test]
+    fn touches_host() {
+        let r = change(
+            1,
+            &["127.0.0.1\tlocalhost", "# a.test is mine"],
+            &["10.0.0.1 a.test b.test", "#10.0.0.2 c.test # old"],
+        );
+        assert!(touches(&r, "localhost"));
+        assert!(touches(&r, "b.test"));
+        assert!(touches(&r, "c.test"));
+        assert!(!touches(&r, "test"));
+        assert!(!touches(&r, "aXtest"));
+        assert!(!touches(&r, "mine"));
+    }
+
+    #[test]
+    fn last_added_line() {
+        let records = vec![
+            change(1, &[], &["10.0.0.1 a.test"]),
+            change(2, &["10.0.0.1 a.test"], &["#10.0.0.1 a.test"]),
+            change(3, &["#10.0.0.1 a.test"], &["10.0.0.1 a.test"]),
+        ];
+        assert_eq!(
+            last_added(&records, "10.0.0.1  a.test").map(|r| r.id),
+            Some(3)
+        );
+        assert_eq!(
+            last_added(&records, "#10.0.0.1 a.test").map(|r| r.id),
+            Some(2)
+        );
+        assert_eq!(last_added(&records, "10.0.0.2 b.test"), None);
+    }
+
+    #[test]
+    fn record_round_trip() {
+        let r = record(3, Action::Undo { of: 2 });
+        let json = serde_json::to_string(&r).unwrap();
+        assert!(json.contains("\"action\":\"undo\""));
+        assert_eq!(serde_json::from_str::<Record>(&json).unwrap(), r);
+    }
+}
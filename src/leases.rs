@@ -0,0 +1,240 @@
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use serde::Deserialize;
+
+/// A DHCP lease with a host name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lease {
+    pub ip: String,
+    pub hostname: String,
+}
+
+/// An entry of a libvirt `<bridge>.status` file.
+#[derive(Deserialize)]
+struct LibvirtLease {
+    #[serde(rename = "ip-address")]
+    ip: String,
+    #[serde(default)]
+    hostname: Option<String>,
+    #[serde(rename = "expiry-time", default)]
+    expiry: Option<i64>,
+}
+
+/// Parses a dnsmasq `.leases`, ISC `dhcpd.leases` or libvirt `.status` file,
+/// telling them apart by their contents. Leases without a host name or that
+/// expired before `now` are left out; when a host name has more than one IPv4
+/// or IPv6 lease the last one wins.
+pub fn parse(contents: &str, now: DateTime<Utc>) -> Result<Vec<Lease>, String> {
+    let trimmed = contents.trim_start();
+    let leases = if trimmed.starts_with('[') {
+        libvirt(contents, now)?
+    } else if trimmed
+        .lines()
+        .any(|l| l.trim_start().starts_with("lease "))
+    {
+        isc(contents, now)?
+    } else {
+        dnsmasq(contents, now)?
+    };
+    let mut latest: Vec<Lease> = vec![];
+    for lease in leases {
+        let hostname = lease.hostname.trim_end_matches('.').to_lowercase();
+        if !is_hostname(&hostname) {
+            continue;
+        }
+        let is_v6 = lease.ip.contains(':');
+        latest.retain(|l| l.hostname != hostname || l.ip.contains(':') != is_v6);
+        latest.push(Lease {
+            ip: lease.ip,
+            hostname,
+        });
+    }
+    Ok(latest)
+}
+
+fn is_hostname(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+}
+
+fn expired(expiry: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+    expiry.is_some_and(|t| t < now)
+}
+
+/// `<expiry> <mac or iaid> <ip> <hostname> <client id>` lines, where an expiry
+/// of 0 means never. The `duid` line of IPv6 leases is skipped.
+fn dnsmasq(contents: &str, now: DateTime<Utc>) -> Result<Vec<Lease>, String> {
+    let mut leases = vec![];
+    for (i, line) in contents.lines().enumerate() {
+        let fields = line.split_whitespace().collect::<Vec<&str>>();
+        if fields.is_empty() || fields[0] == "duid" {
+            continue;
+        }
+        if fields.len() < 4 {
+            return Err(format!("line {}: expected at least 4 fields", i + 1));
+        }
+        let invalid = format!("line {}: invalid expiry time {}", i + 1, fields[0]);
+        let expiry = match fields[0].parse::<i64>() {
+            Ok(0) => None,
+            Ok(seconds) => Some(timestamp(seconds).ok_or(invalid)?),
+            Err(_) => return Err(invalid),
+        };
+        if fields[3] != "*" && !expired(expiry, now) {
+            leases.push(Lease {
+                ip: String::from(fields[2]),
+                hostname: String::from(fields[3]),
+            });
+        }
+    }
+    Ok(leases)
+}
+
+/// `lease <ip> { ... }` blocks with `client-hostname`, `ends` and
+/// `binding state` statements. Later blocks for an address replace earlier ones.
+fn isc(contents: &str, now: DateTime<Utc>) -> Result<Vec<Lease>, String> {
+    let mut leases: Vec<Lease> = vec![];
+    let mut current: Option<(String, Option<String>, bool)> = None;
+    for line in contents.lines() {
+        let line = line.trim().trim_end_matches(';');
+        if let Some(rest) = line.strip_prefix("lease ") {
+            let ip = rest.trim_end_matches('{').trim();
+            current = Some((String::from(ip), None, true));
+        } else if let Some((ip, hostname, active)) = current.as_mut() {
+            if let Some(name) = line.strip_prefix("client-hostname ") {
+                *hostname = Some(String::from(name.trim_matches('"')));
+            } else if let Some(state) = line.strip_prefix("binding state ") {
+                *active = state == "active";
+            } else if let Some(ends) = line.strip_prefix("ends ") {
+                if ends != "never" {
+                    let date = ends
+                        .split_once(' ')
+                        .map(|(_, date)| date)
+                        .unwrap_or_default();
+                    let ends = NaiveDateTime::parse_from_str(date, "%Y/%m/%d %H:%M:%S")
+                        .map_err(|_| format!("lease {}: invalid end time {}", ip, ends))?;
+                    *active = *active && !expired(Some(Utc.from_utc_datetime(&ends)), now);
+                }
+            } else if line == "}" {
+                let (ip, hostname, active) = current.take().unwrap();
+                leases.retain(|l| l.ip != ip);
+                if let Some(hostname) = hostname.filter(|_| active) {
+                    leases.push(Lease { ip, hostname });
+                }
+            }
+        }
+    }
+    Ok(leases)
+}
+
+fn libvirt(contents: &str, now: DateTime<Utc>) -> Result<Vec<Lease>, String> {
+    let entries: Vec<LibvirtLease> = serde_json::from_str(contents).map_err(|e| e.to_string())?;
+    let mut leases = vec![];
+    for LibvirtLease {
+        ip,
+        hostname,
+        expiry,
+    } in entries
+    {
+        let expiry = expiry
+            .map(|seconds| timestamp(seconds).ok_or(format!("invalid expiry time {}", seconds)))
+            .transpose()?;
+        if let Some(hostname) = hostname.filter(|_| !expired(expiry, now)) {
+            leases.push(Lease { ip, hostname });
+        }
+    }
+    Ok(leases)
+}
+
+/// Returns the time of a unix timestamp, or `None` if it is out of range.
+fn timestamp(seconds: i64) -> Option<DateTime<Utc>> {
+    Utc.timestamp_opt(seconds, 0).single()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> DateTime<Utc> {
+        Utc.timestamp_opt(1_800_000_000, 0).unwrap()
+    }
+
+    fn lease(ip: &str, hostname: &str) -> Lease {
+        Lease {
+            ip: String::from(ip),
+            hostname: String::from(hostname),
+        }
+    }
+
+    #[test]
+    fn dnsmasq_leases() {
+        let contents = "\
+1800003600 52:54:00:aa:bb:01 192.168.122.10 web01 01:52:54:00:aa:bb:01
+0 52:54:00:aa:bb:02 192.168.122.11 DB01 *
+1700000000 52:54:00:aa:bb:03 192.168.122.12 gone *
+1800003600 52:54:00:aa:bb:04 192.168.122.13 * *
+duid 00:01:00:01:2c:4f:11:aa:52:54:00:aa:bb:01
+1800003600 1234 fd00::10 web01 00:01:00:01
+";
+        assert_eq!(
+            parse(contents, now()).unwrap(),
+            vec![
+                lease("192.168.122.10", "web01"),
+                lease("192.168.122.11", "db01"),
+                lease("fd00::10", "web01")
+            ]
+        );
+        assert!(parse("soon aa 10.0.0.1 x *\n", now()).is_err());
+        assert!(parse("99999999999999 aa 10.0.0.1 x *\n", now()).is_err());
+    }
+
+    #[test]
+    fn isc_leases() {
+        let contents = r#"
+# The format of this file is documented in the dhcpd.leases(5) manual page.
+lease 10.0.0.20 {
+  starts 4 2027/01/14 08:00:00;
+  ends 6 2027/01/16 09:00:00;
+  binding state active;
+  client-hostname "laptop";
+}
+lease 10.0.0.21 {
+  ends never;
+  binding state active;
+  client-hostname "printer";
+}
+lease 10.0.0.21 {
+  ends never;
+  binding state free;
+}
+lease 10.0.0.22 {
+  ends 1 2020/01/01 00:00:00;
+  client-hostname "old";
+}
+"#;
+        assert_eq!(
+            parse(contents, now()).unwrap(),
+            vec![lease("10.0.0.20", "laptop")]
+        );
+    }
+
+    #[test]
+    fn libvirt_status() {
+        let contents = r#"[
+  {"ip-address": "192.168.122.50", "mac-address": "52:54:00:01:02:03",
+   "hostname": "vm1", "expiry-time": 1800003600},
+  {"ip-address": "192.168.122.51", "mac-address": "52:54:00:01:02:04",
+   "expiry-time": 1800003600},
+  {"ip-address": "192.168.122.52", "hostname": "vm1", "expiry-time": 1800007200}
+]"#;
+        assert_eq!(
+            parse(contents, now()).unwrap(),
+            vec![lease("192.168.122.52", "vm1")]
+        );
+        assert!(parse(
+            r#"[{"ip-address": "10.0.0.1", "expiry-time": -99999999999999}]"#,
+            now()
+        )
+        .is_err());
+    }
+}
@@ -4,24 +4,118 @@ extern crate hosts_parser;
 extern crate regex; // not needed in Rust 2018
 
 use structopt::StructOpt;
+mod batch;
+mod block;
+mod blocklist;
 mod cli;
-use cli::{Cli, CliCmd};
+use cli::{Cli, CliCmd, CmdDaemon, CmdImport};
 mod commands;
+mod containers;
+mod daemon;
+mod diff;
+mod dns;
+mod drift;
+mod entry;
 mod file_utils;
 mod hostsfile;
+mod journal;
+mod leases;
+mod manifests;
+mod metadata;
+mod picker;
+mod schedule;
+mod tui;
+mod vhosts;
+mod zone;
 // mod nom_hosts;
 
 fn main() {
     let args = Cli::from_args();
 
     match &args.cmd {
-        CliCmd::Show { summary } => commands::show(*summary),
-        CliCmd::Check { host, exact } => commands::check(&host, *exact),
+        CliCmd::Show { summary, comment } => commands::show(*summary, comment.as_deref()),
+        CliCmd::Check { host, exact } => commands::check(host, *exact),
         CliCmd::Add(sub_cmd) => commands::add(&args, sub_cmd),
         CliCmd::AddLocal(sub_cmd) => commands::add_local(&args, sub_cmd),
-        CliCmd::Remove { host } => commands::remove(&args, &host),
-        CliCmd::Disable { host } => commands::disable(&args, &host),
-        CliCmd::Enable { host } => commands::enable(&args, &host),
+        CliCmd::Alias(sub_cmd) => commands::alias(&args, sub_cmd),
+        CliCmd::Move { host, ip, split } => commands::move_host(&args, host, ip, *split),
+        CliCmd::Rename { old, new, suffix } => {
+            commands::rename(&args, old.as_deref(), new.as_deref(), suffix.as_ref())
+        }
+        CliCmd::Comment { host, text } => commands::comment(&args, host, text),
+        CliCmd::Remove { host } => commands::remove(&args, host),
+        CliCmd::Disable { host } => commands::disable(&args, host),
+        CliCmd::Enable { host } => commands::enable(&args, host),
+        CliCmd::Toggle { host } => commands::toggle(&args, host.as_deref()),
+        CliCmd::Prune { disable } => commands::prune(&args, *disable),
+        CliCmd::Run(sub_cmd) => commands::run(&args, sub_cmd),
+        CliCmd::Undo {} => commands::undo(&args),
+        CliCmd::Redo {} => commands::redo(&args),
+        CliCmd::Edit {} => commands::edit(&args),
+        CliCmd::Block(sub_cmd) => commands::block(&args, sub_cmd),
+        CliCmd::Unblock { domains } => commands::unblock(&args, domains),
+        CliCmd::Daemon {
+            cmd: Some(CmdDaemon::Status {}),
+            ..
+        } => commands::daemon_status(),
+        CliCmd::Daemon {
+            interval,
+            debounce,
+            enforce,
+            cmd: None,
+        } => commands::daemon(&args, *interval, *debounce, enforce.as_deref()),
+        CliCmd::Tui {} => commands::tui(&args),
+        CliCmd::Export {
+            format,
+            filter,
+            host,
+            exact,
+            all,
+            wildcard,
+            output,
+            origin,
+            nameserver,
+            ptr,
+        } => {
+            let selection = commands::Selection {
+                filter: filter.as_deref(),
+                host: host.as_deref(),
+                exact: *exact,
+                all: *all,
+            };
+            let zone = commands::ZoneOptions {
+                origin: origin.as_deref(),
+                nameserver: nameserver.as_deref(),
+                ptr: *ptr,
+            };
+            commands::export(&selection, *format, *wildcard, output.as_deref(), &zone)
+        }
+        CliCmd::Import {
+            file,
+            format,
+            options,
+            source,
+        } => match (source, file) {
+            (Some(source), None) => commands::import_source(&args, source, options.on_conflict),
+            (None, Some(file)) => {
+                commands::import(&args, file, *format, options.on_conflict.unwrap_or_default())
+            }
+            (Some(CmdImport::Extra(extra)), Some(_)) => {
+                eprintln!("Error: unexpected argument: {}", extra.join(" "));
+                std::process::exit(1);
+            }
+            (Some(_), Some(file)) => {
+                eprintln!("Error: cannot import {} and a subcommand at once", file);
+                std::process::exit(1);
+            }
+            (None, None) => unreachable!("clap requires the file"),
+        },
+        CliCmd::Batch { file } => commands::batch(&args, file.as_deref()),
+        CliCmd::Blocklist(sub_cmd) => commands::blocklist(&args, sub_cmd),
+        CliCmd::Dedupe { max_aliases } => commands::dedupe(&args, *max_aliases),
+        CliCmd::Fmt { sort, check } => commands::fmt(&args, *sort, *check),
+        CliCmd::History { host } => commands::history(host.as_deref()),
+        CliCmd::Blame {} => commands::blame(),
         CliCmd::Update {} => commands::update(),
         // _ => println!("Not implemented"),
     }
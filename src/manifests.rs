@@ -0,0 +1,218 @@
+use regex::Regex;
+use serde::Deserialize;
+use serde_yaml::Value;
+
+/// Returns the host names a docker-compose file declares: `hostname`,
+/// `extra_hosts` and the `VIRTUAL_HOST` variable or label of nginx-proxy, and
+/// the `Host` rules of Traefik labels.
+pub fn compose_hosts(contents: &str) -> Result<Vec<String>, String> {
+    let doc: Value = serde_yaml::from_str(contents).map_err(|e| e.to_string())?;
+    let services = doc
+        .get("services")
+        .and_then(Value::as_mapping)
+        .ok_or("no services found")?;
+    let mut hosts = vec![];
+    for (_, service) in services.iter() {
+        if let Some(hostname) = service.get("hostname").and_then(Value::as_str) {
+            hosts.push(String::from(hostname));
+        }
+        match service.get("extra_hosts") {
+            Some(Value::Sequence(items)) => hosts.extend(
+                items
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .filter_map(|item| item.split([':', '=']).next())
+                    .map(String::from),
+            ),
+            Some(Value::Mapping(items)) => hosts.extend(
+                items
+                    .iter()
+                    .filter_map(|(k, _)| k.as_str())
+                    .map(String::from),
+            ),
+            _ => {}
+        }
+        for (key, value) in pairs(service.get("labels"))
+            .into_iter()
+            .chain(pairs(service.get("environment")))
+        {
+            if key == "VIRTUAL_HOST" {
+                hosts.extend(value.split(',').map(|h| String::from(h.trim())));
+            } else if key.starts_with("traefik.") && key.ends_with(".rule") {
+                hosts.extend(traefik_hosts(&value));
+            }
+        }
+    }
+    Ok(clean(hosts))
+}
+
+/// Returns the hosts of every Ingress in a (multi-document) kubernetes
+/// manifest, including those in `List` resources.
+pub fn ingress_hosts(contents: &str) -> Result<Vec<String>, String> {
+    let mut hosts = vec![];
+    for document in serde_yaml::Deserializer::from_str(contents) {
+        let doc = Value::deserialize(document).map_err(|e| e.to_string())?;
+        collect_ingress_hosts(&doc, &mut hosts);
+    }
+    Ok(clean(hosts))
+}
+
+fn collect_ingress_hosts(doc: &Value, hosts: &mut Vec<String>) {
+    match doc.get("kind").and_then(Value::as_str) {
+        Some("List") => {
+            for item in doc
+                .get("items")
+                .and_then(Value::as_sequence)
+                .into_iter()
+                .flatten()
+            {
+                collect_ingress_hosts(item, hosts);
+            }
+        }
+        Some("Ingress") => {
+            let spec = doc.get("spec");
+            let rules = spec
+                .and_then(|s| s.get("rules"))
+                .and_then(Value::as_sequence);
+            for rule in rules.into_iter().flatten() {
+                hosts.extend(rule.get("host").and_then(Value::as_str).map(String::from));
+            }
+            let tls = spec.and_then(|s| s.get("tls")).and_then(Value::as_sequence);
+            for tls in tls.into_iter().flatten() {
+                let names = tls.get("hosts").and_then(Value::as_sequence);
+                hosts.extend(
+                    names
+                        .into_iter()
+                        .flatten()
+                        .filter_map(Value::as_str)
+                        .map(String::from),
+                );
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Compose labels and environments are either a map or a list of `KEY=value`.
+fn pairs(value: Option<&Value>) -> Vec<(String, String)> {
+    match value {
+        Some(Value::Mapping(map)) => map
+            .iter()
+            .filter_map(|(k, v)| Some((String::from(k.as_str()?), String::from(v.as_str()?))))
+            .collect(),
+        Some(Value::Sequence(items)) => items
+            .iter()
+            .filter_map(Value::as_str)
+            .filter_map(|item| {
+                let mut parts = item.splitn(2, '=');
+                Some((String::from(parts.next()?), String::from(parts.next()?)))
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// The names of `Host(`a`, `b`)` (Traefik 2+) and `Host:a,b` (Traefik 1) rules.
+fn traefik_hosts(rule: &str) -> Vec<String> {
+    let v2 = Regex::new(r"Host\(([^)]*)\)").unwrap();
+    let v1 = Regex::new(r"Host:\s*([^;]*)").unwrap();
+    let mut hosts = vec![];
+    for c in v2.captures_iter(rule) {
+        hosts.extend(
+            c[1].split(',')
+                .map(|h| String::from(h.trim().trim_matches(['`', '"']))),
+        );
+    }
+    for c in v1.captures_iter(rule) {
+        hosts.extend(c[1].split(',').map(|h| String::from(h.trim())));
+    }
+    hosts
+}
+
+/// Drops empty and wildcard names and duplicates, keeping the first occurrence.
+fn clean(hosts: Vec<String>) -> Vec<String> {
+    let mut cleaned: Vec<String> = vec![];
+    for host in hosts {
+        if !host.is_empty() && !host.contains('*') && !cleaned.contains(&host) {
+            cleaned.push(host);
+        }
+    }
+    cleaned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compose_file() {
+        let compose = r#"
+services:
+  web:
+    image: nginx
+    hostname: web.test
+    extra_hosts:
+      - "db.test:10.0.0.2"
+      - "cache.test=10.0.0.3"
+    environment:
+      - VIRTUAL_HOST=app.test, www.app.test
+  api:
+    image: api
+    extra_hosts:
+      db.test: 10.0.0.2
+    labels:
+      traefik.http.routers.api.rule: "Host(`api.test`) || Host(`*.api.test`)"
+      traefik.frontend.rule: "Host:old.test,web.test"
+"#;
+        assert_eq!(
+            compose_hosts(compose).unwrap(),
+            vec![
+                "web.test",
+                "db.test",
+                "cache.test",
+                "app.test",
+                "www.app.test",
+                "api.test",
+                "old.test",
+            ]
+        );
+        assert!(compose_hosts("version: '3'\n").is_err());
+    }
+
+    #[test]
+    fn kubernetes_manifests() {
+        let manifests = r#"
+apiVersion: v1
+kind: Service
+metadata:
+  name: web
+spec:
+  rules:
+    - host: not-an-ingress.test
+---
+apiVersion: networking.k8s.io/v1
+kind: Ingress
+spec:
+  tls:
+    - hosts: [secure.test]
+  rules:
+    - host: web.test
+    - host: "*.wild.test"
+    - http: {}
+---
+apiVersion: v1
+kind: List
+items:
+  - kind: Ingress
+    spec:
+      rules:
+        - host: listed.test
+        - host: web.test
+"#;
+        assert_eq!(
+            ingress_hosts(manifests).unwrap(),
+            vec!["web.test", "secure.test", "listed.test"]
+        );
+        assert!(ingress_hosts("kind: [").is_err());
+    }
+}
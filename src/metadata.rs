@@ -0,0 +1,255 @@
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+use regex::Regex;
+
+pub const EXPIRES_TAG: &str = "expires";
+pub const SOURCE_TAG: &str = "source";
+/// Tags the lines hostman writes with a custom comment, as `[by=hostman]`.
+pub const MANAGED_TAG: &str = "by";
+const MANAGED_BY: &str = "hostman";
+/// The comments that mark the lines hostman adds and blocks.
+const MARKERS: [&str; 2] = [crate::commands::DEFAULT_COMMENT, crate::block::BLOCK_COMMENT];
+
+/// Returns the value of a `[key=value]` tag stored in a line comment.
+pub fn get_tag(comment: &str, key: &str) -> Option<String> {
+    tag_matcher(key)
+        .captures(comment)
+        .map(|c| String::from(c[1].trim()))
+}
+
+/// Whether the comment carries any `[key=value]` tag.
+pub fn has_tags(comment: &str) -> bool {
+    any_tag_matcher().is_match(comment)
+}
+
+/// Returns the human readable part of a comment, without `#` and tags.
+pub fn text(comment: &str) -> String {
+    let stripped = any_tag_matcher().replace_all(comment, "");
+    let stripped = stripped.trim().trim_start_matches('#');
+    stripped.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// Returns a comment with the given text, keeping the tags of `comment`.
+pub fn with_text(comment: &str, text: &str) -> String {
+    let text = text.trim().trim_start_matches('#').trim();
+    let mut parts: Vec<&str> = vec![];
+    if !text.is_empty() {
+        parts.push(text);
+    }
+    parts.extend(any_tag_matcher().find_iter(comment).map(|m| m.as_str()));
+    if parts.is_empty() {
+        String::new()
+    } else {
+        format!("# {}", parts.join(" "))
+    }
+}
+
+/// Returns the comment with the `[key=value]` tag set, replacing any previous value.
+pub fn with_tag(comment: &str, key: &str, value: &str) -> String {
+    let comment = without_tag(comment, key);
+    if comment.is_empty() {
+        format!("[{}={}]", key, value)
+    } else {
+        format!("{} [{}={}]", comment, key, value)
+    }
+}
+
+/// Returns the comment with the tag of the lines hostman writes, unless it
+/// already has one of hostman's markers.
+pub fn with_managed_tag(comment: &str) -> String {
+    if has_marker(comment) {
+        String::from(comment)
+    } else {
+        with_tag(comment, MANAGED_TAG, MANAGED_BY)
+    }
+}
+
+/// Whether hostman wrote the line of this comment, as told by its marker or tag.
+pub fn is_managed(comment: &str) -> bool {
+    has_marker(comment) || get_tag(comment, MANAGED_TAG).is_some_and(|v| v == MANAGED_BY)
+}
+
+fn has_marker(comment: &str) -> bool {
+    let text = text(comment);
+    MARKERS.iter().any(|m| text.contains(m))
+}
+
+/// Returns the comment with the `[key=value]` tag removed.
+pub fn without_tag(comment: &str, key: &str) -> String {
+    let stripped = tag_matcher(key).replace_all(comment, "");
+    stripped.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// Returns the expiry time recorded in a line comment, if any.
+pub fn expires(comment: &str) -> Option<DateTime<Utc>> {
+    get_tag(comment, EXPIRES_TAG)
+        .and_then(|value| DateTime::parse_from_rfc3339(&value).ok())
+        .map(|t| t.with_timezone(&Utc))
+}
+
+/// Returns the comment with its expiry time set.
+pub fn with_expiry(comment: &str, expires: DateTime<Utc>) -> String {
+    with_tag(
+        comment,
+        EXPIRES_TAG,
+        &expires.to_rfc3339_opts(SecondsFormat::Secs, true),
+    )
+}
+
+/// Returns when a line added now should expire: `ttl` from `now`, or `until`.
+/// Fails for a time that is out of range or already past.
+pub fn new_expiry(
+    ttl: Option<std::time::Duration>,
+    until: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> Result<Option<DateTime<Utc>>, String> {
+    let expires = match (ttl, until) {
+        (Some(ttl), _) => chrono::Duration::from_std(ttl)
+            .ok()
+            .and_then(|d| now.checked_add_signed(d))
+            .ok_or_else(|| format!("duration is too long: {}", humantime::format_duration(ttl)))?,
+        (_, Some(until)) => until,
+        _ => return Ok(None),
+    };
+    if expires <= now {
+        return Err(format!(
+            "{} is in the past",
+            expires.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S")
+        ));
+    }
+    Ok(Some(expires))
+}
+
+/// Parses an RFC 3339 timestamp, or a local `YYYY-MM-DD[ HH:MM[:SS]]` time.
+pub fn parse_timestamp(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(t) = DateTime::parse_from_rfc3339(value) {
+        return Ok(t.with_timezone(&Utc));
+    }
+    let naive = [
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%dT%H:%M",
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+    .or_else(|| {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .ok()
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+    });
+    naive
+        .and_then(|n| Local.from_local_datetime(&n).earliest())
+        .map(|t| t.with_timezone(&Utc))
+        .ok_or_else(|| format!("invalid timestamp: {}", value))
+}
+
+fn any_tag_matcher() -> Regex {
+    Regex::new(r"\[[\w-]+=[^\]]*\]").unwrap()
+}
+
+fn tag_matcher(key: &str) -> Regex {
+    Regex::new(format!(r"\[{}=([^\]]*)\]", regex::escape(key)).as_str()).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_tag_value() {
+        let comment = "# Added by hostman [run=123-456] [other=x y]";
+        assert_eq!(get_tag(comment, "run"), Some(String::from("123-456")));
+        assert_eq!(get_tag(comment, "other"), Some(String::from("x y")));
+        assert_eq!(get_tag(comment, "missing"), None);
+    }
+
+    #[test]
+    fn detect_tags() {
+        assert!(has_tags("# Added by hostman [run=1]"));
+        assert!(!has_tags("# Added by hostman [draft]"));
+    }
+
+    #[test]
+    fn comment_text() {
+        assert_eq!(text("# my app [run=1]"), "my app");
+        assert_eq!(text("#[run=1]"), "");
+        assert_eq!(
+            with_text("# Added by hostman [expires=x]", "my app"),
+            "# my app [expires=x]"
+        );
+        assert_eq!(with_text("# Added by hostman", ""), "");
+        assert_eq!(with_text("", "# note"), "# note");
+    }
+
+    #[test]
+    fn set_tag() {
+        assert_eq!(with_tag("", "run", "1"), "[run=1]");
+        assert_eq!(
+            with_tag("# Added by hostman", "run", "1"),
+            "# Added by hostman [run=1]"
+        );
+        assert_eq!(
+            with_tag("# Added by hostman [run=1]", "run", "2"),
+            "# Added by hostman [run=2]"
+        );
+        assert!(is_managed(&with_managed_tag("# my app")));
+        assert_eq!(
+            with_managed_tag("# Added by hostman [run=1]"),
+            "# Added by hostman [run=1]"
+        );
+        assert!(is_managed("# Added by hostman"));
+        assert!(is_managed("# Blocked by hostman [block=ads.test]"));
+        assert!(!is_managed("# [by=someone]"));
+    }
+
+    #[test]
+    fn remove_tag() {
+        assert_eq!(
+            without_tag("# Added by hostman [run=1] [other=2]", "run"),
+            "# Added by hostman [other=2]"
+        );
+        assert_eq!(without_tag("# comment", "run"), "# comment");
+    }
+
+    #[test]
+    fn expiry() {
+        let t = Utc.with_ymd_and_hms(2026, 10, 19, 17, 0, 0).unwrap();
+        let comment = with_expiry("# Added by hostman", t);
+        assert_eq!(comment, "# Added by hostman [expires=2026-10-19T17:00:00Z]");
+        assert_eq!(expires(&comment), Some(t));
+        assert_eq!(expires("# Added by hostman"), None);
+        assert_eq!(expires("# [expires=soon]"), None);
+    }
+
+    #[test]
+    fn expiry_times() {
+        let now = Utc.with_ymd_and_hms(2026, 10, 19, 17, 0, 0).unwrap();
+        let hour = std::time::Duration::from_secs(3600);
+        assert_eq!(
+            new_expiry(Some(hour), None, now),
+            Ok(Some(Utc.with_ymd_and_hms(2026, 10, 19, 18, 0, 0).unwrap()))
+        );
+        assert!(new_expiry(
+            Some(humantime::parse_duration("300000y").unwrap()),
+            None,
+            now
+        )
+        .is_err());
+        assert!(new_expiry(None, Some(now), now).is_err());
+        assert_eq!(new_expiry(None, None, now), Ok(None));
+    }
+
+    #[test]
+    fn timestamps() {
+        let t = Utc.with_ymd_and_hms(2026, 10, 19, 17, 0, 0).unwrap();
+        assert_eq!(parse_timestamp("2026-10-19T17:00:00Z"), Ok(t));
+        assert_eq!(parse_timestamp("2026-10-19T19:00:00+02:00"), Ok(t));
+        let local = Local.with_ymd_and_hms(2026, 10, 19, 17, 0, 0).unwrap();
+        assert_eq!(
+            parse_timestamp("2026-10-19 17:00"),
+            Ok(local.with_timezone(&Utc))
+        );
+        assert!(parse_timestamp("2026-10-19").is_ok());
+        assert!(parse_timestamp("tomorrow").is_err());
+    }
+}
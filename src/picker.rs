@@ -0,0 +1,232 @@
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+/// Scores how well `pattern` matches `candidate` as a case-insensitive
+/// subsequence. Consecutive characters and matches at the start of a word score
+/// higher. Returns `None` if the pattern does not match.
+pub fn fuzzy_score(pattern: &str, candidate: &str) -> Option<i64> {
+    let candidate: Vec<char> = candidate.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut next = 0;
+    let mut previous: Option<usize> = None;
+    for p in pattern
+        .to_lowercase()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+    {
+        let found = next + candidate[next..].iter().position(|c| *c == p)?;
+        score += 1;
+        if found == 0 || !candidate[found - 1].is_alphanumeric() {
+            score += 3;
+        }
+        match previous {
+            Some(i) if i + 1 == found => score += 5,
+            Some(i) => score -= (found - i - 1).min(5) as i64,
+            None => {}
+        }
+        previous = Some(found);
+        next = found + 1;
+    }
+    Some(score)
+}
+
+/// Returns the indices of the items matching `pattern`, best match first.
+pub fn filter(pattern: &str, items: &[String]) -> Vec<usize> {
+    let mut matches: Vec<(i64, usize)> = items
+        .iter()
+        .enumerate()
+        .filter_map(|(i, item)| fuzzy_score(pattern, item).map(|score| (-score, i)))
+        .collect();
+    matches.sort();
+    matches.into_iter().map(|(_, i)| i).collect()
+}
+
+/// Switches to raw mode on the alternate screen, restoring the terminal when
+/// dropped, even on panic.
+pub struct RawTerminal;
+
+impl RawTerminal {
+    pub fn enter() -> io::Result<RawTerminal> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stderr(), EnterAlternateScreen, Hide)?;
+        Ok(RawTerminal)
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let _ = execute!(io::stderr(), Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Lets the user filter `items` by typing and pick any number of them. Returns
+/// the selected indices in their original order, or `None` if cancelled.
+pub fn multi_select(prompt: &str, items: &[String]) -> io::Result<Option<Vec<usize>>> {
+    let _terminal = RawTerminal::enter()?;
+    let mut query = String::new();
+    let mut selected: HashSet<usize> = HashSet::new();
+    let mut cursor = 0;
+    let mut offset = 0;
+    loop {
+        let visible = filter(&query, items);
+        cursor = cursor.min(visible.len().saturating_sub(1));
+        let (_, height) = terminal::size()?;
+        let rows = (height as usize).saturating_sub(3).max(1);
+        if cursor < offset {
+            offset = cursor;
+        } else if cursor >= offset + rows {
+            offset = cursor + 1 - rows;
+        }
+        draw(
+            prompt, &query, items, &visible, &selected, cursor, offset, rows,
+        )?;
+
+        let key = match event::read()? {
+            Event::Key(key) if key.kind != KeyEventKind::Release => key,
+            _ => continue,
+        };
+        match key {
+            KeyEvent {
+                code: KeyCode::Char('c'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            }
+            | KeyEvent {
+                code: KeyCode::Esc, ..
+            } => return Ok(None),
+            KeyEvent {
+                code: KeyCode::Enter,
+                ..
+            } => {
+                if selected.is_empty() {
+                    selected.extend(visible.get(cursor));
+                }
+                let mut picked: Vec<usize> = selected.into_iter().collect();
+                picked.sort_unstable();
+                return Ok(Some(picked));
+            }
+            KeyEvent {
+                code: KeyCode::Up, ..
+            } => cursor = cursor.saturating_sub(1),
+            KeyEvent {
+                code: KeyCode::Down,
+                ..
+            } => cursor += 1,
+            KeyEvent {
+                code: KeyCode::Char(' '),
+                ..
+            }
+            | KeyEvent {
+                code: KeyCode::Tab, ..
+            } => {
+                if let Some(item) = visible.get(cursor) {
+                    if !selected.remove(item) {
+                        selected.insert(*item);
+                    }
+                    cursor += 1;
+                }
+            }
+            KeyEvent {
+                code: KeyCode::Backspace,
+                ..
+            } => {
+                query.pop();
+            }
+            KeyEvent {
+                code: KeyCode::Char(c),
+                modifiers,
+                ..
+            } if !modifiers.contains(KeyModifiers::CONTROL) => {
+                query.push(c);
+                cursor = 0;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn draw(
+    prompt: &str,
+    query: &str,
+    items: &[String],
+    visible: &[usize],
+    selected: &HashSet<usize>,
+    cursor: usize,
+    offset: usize,
+    rows: usize,
+) -> io::Result<()> {
+    let mut out = io::stderr();
+    queue!(
+        out,
+        MoveTo(0, 0),
+        Clear(ClearType::All),
+        Print(format!("{} > {}", prompt, query)),
+    )?;
+    for (row, (position, item)) in visible
+        .iter()
+        .enumerate()
+        .skip(offset)
+        .take(rows)
+        .enumerate()
+    {
+        let mark = if selected.contains(item) {
+            "[x]"
+        } else {
+            "[ ]"
+        };
+        queue!(out, MoveTo(0, row as u16 + 1))?;
+        if position == cursor {
+            queue!(out, SetAttribute(Attribute::Reverse))?;
+        }
+        queue!(
+            out,
+            Print(format!("{} {}", mark, items[*item])),
+            SetAttribute(Attribute::Reset)
+        )?;
+    }
+    queue!(
+        out,
+        MoveTo(0, rows as u16 + 1),
+        Print(format!(
+            "{}/{} shown, {} selected. space: select, enter: confirm, esc: cancel",
+            visible.len(),
+            items.len(),
+            selected.len()
+        )),
+    )?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fuzzy_matching() {
+        assert!(fuzzy_score("apt", "app.test").is_some());
+        assert!(fuzzy_score("APP", "app.test").is_some());
+        assert!(fuzzy_score("tpa", "app.test").is_none());
+        assert_eq!(fuzzy_score("", "app.test"), Some(0));
+        assert!(fuzzy_score("app", "app.test") > fuzzy_score("app", "a.p.p.test"));
+    }
+
+    #[test]
+    fn filter_ranks_matches() {
+        let items = vec![
+            String::from("10.0.0.1 api.test"),
+            String::from("127.0.0.1 localhost"),
+            String::from("10.0.0.2 app.test"),
+        ];
+        assert_eq!(filter("app", &items), vec![2]);
+        assert_eq!(filter("apt", &items), vec![0, 2]);
+        assert_eq!(filter("", &items), vec![0, 1, 2]);
+        assert!(filter("xyz", &items).is_empty());
+    }
+}
@@ -0,0 +1,125 @@
+use chrono::{Datelike, Duration, NaiveDateTime, NaiveTime, Weekday};
+use std::fmt;
+
+pub const SCHEDULE_TAG: &str = "schedule";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Days {
+    Daily,
+    Weekdays,
+    Weekends,
+}
+
+impl Days {
+    fn includes(self, day: Weekday) -> bool {
+        let weekend = matches!(day, Weekday::Sat | Weekday::Sun);
+        match self {
+            Days::Daily => true,
+            Days::Weekdays => !weekend,
+            Days::Weekends => weekend,
+        }
+    }
+}
+
+/// A recurring daily time window, such as weekdays from 09:00 to 17:00. A
+/// window that ends before it starts runs past midnight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Schedule {
+    pub days: Days,
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl Schedule {
+    /// Whether the window is open at the given local time.
+    pub fn is_active(&self, now: NaiveDateTime) -> bool {
+        let time = now.time();
+        if self.start <= self.end {
+            return self.days.includes(now.weekday()) && time >= self.start && time < self.end;
+        }
+        let yesterday = (now - Duration::days(1)).weekday();
+        (self.days.includes(now.weekday()) && time >= self.start)
+            || (self.days.includes(yesterday) && time < self.end)
+    }
+}
+
+/// Parses a `HH:MM-HH:MM` time range.
+pub fn parse_range(value: &str) -> Result<(NaiveTime, NaiveTime), String> {
+    let invalid = || format!("expected HH:MM-HH:MM, got {}", value);
+    let (start, end) = value.split_once('-').ok_or_else(invalid)?;
+    let time = |t: &str| NaiveTime::parse_from_str(t.trim(), "%H:%M").map_err(|_| invalid());
+    let (start, end) = (time(start)?, time(end)?);
+    if start == end {
+        return Err(format!("empty time range: {}", value));
+    }
+    Ok((start, end))
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let days = match self.days {
+            Days::Daily => "daily",
+            Days::Weekdays => "weekdays",
+            Days::Weekends => "weekends",
+        };
+        write!(
+            f,
+            "{} {}-{}",
+            days,
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M")
+        )
+    }
+}
+
+impl std::str::FromStr for Schedule {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Schedule, String> {
+        let (days, range) = s
+            .split_once(' ')
+            .ok_or_else(|| format!("invalid schedule: {}", s))?;
+        let days = match days {
+            "daily" => Days::Daily,
+            "weekdays" => Days::Weekdays,
+            "weekends" => Days::Weekends,
+            _ => return Err(format!("invalid schedule days: {}", days)),
+        };
+        let (start, end) = parse_range(range)?;
+        Ok(Schedule { days, start, end })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn parse_and_display() {
+        let schedule: Schedule = "weekdays 09:00-17:30".parse().unwrap();
+        assert_eq!(schedule.days, Days::Weekdays);
+        assert_eq!(schedule.to_string(), "weekdays 09:00-17:30");
+        assert!("weekdays 9-5".parse::<Schedule>().is_err());
+        assert!("sometimes 09:00-17:00".parse::<Schedule>().is_err());
+        assert!(parse_range("10:00-10:00").is_err());
+    }
+
+    #[test]
+    fn active_windows() {
+        // 2026-10-16 is a Friday.
+        let work: Schedule = "weekdays 09:00-17:00".parse().unwrap();
+        assert!(work.is_active(at("2026-10-16 09:00")));
+        assert!(!work.is_active(at("2026-10-16 17:00")));
+        assert!(!work.is_active(at("2026-10-17 10:00")));
+
+        let night: Schedule = "weekdays 22:00-06:00".parse().unwrap();
+        assert!(night.is_active(at("2026-10-16 23:00")));
+        assert!(night.is_active(at("2026-10-17 05:59")));
+        assert!(!night.is_active(at("2026-10-17 23:00")));
+        assert!(!night.is_active(at("2026-10-19 05:00")));
+        assert!(!night.is_active(at("2026-10-16 12:00")));
+    }
+}
@@ -0,0 +1,387 @@
+use crate::diff;
+use crate::hostsfile::ManagedHostsFile;
+use crate::metadata;
+use crate::picker::{self, RawTerminal};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Clear, Paragraph, Row, Table, TableState};
+use ratatui::{Frame, Terminal};
+use std::io;
+
+const FIELDS: [&str; 3] = ["ip", "names", "comment"];
+
+/// An entry being edited, or added when `index` is `None`.
+struct Form {
+    index: Option<usize>,
+    values: [String; 3],
+    field: usize,
+    comment: String,
+}
+
+enum Mode {
+    Browse,
+    Search,
+    Edit(Form),
+    ConfirmQuit,
+}
+
+struct App {
+    hosts_file: ManagedHostsFile,
+    query: String,
+    cursor: usize,
+    mode: Mode,
+    message: String,
+}
+
+/// The row shown for an entry: whether it is enabled, its ip, names and comment.
+struct Entry {
+    index: usize,
+    enabled: bool,
+    ip: String,
+    names: String,
+    comment: String,
+}
+
+/// Opens the editor on `hosts_file`. Returns the edited file when the user asks
+/// to save, or `None` when they quit without saving.
+pub fn run(hosts_file: ManagedHostsFile) -> io::Result<Option<ManagedHostsFile>> {
+    let _raw = RawTerminal::enter()?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stderr()))?;
+    let mut app = App {
+        hosts_file,
+        query: String::new(),
+        cursor: 0,
+        mode: Mode::Browse,
+        message: String::new(),
+    };
+    loop {
+        terminal.draw(|f| draw(f, &app))?;
+        let key = match event::read()? {
+            Event::Key(key) if key.kind != KeyEventKind::Release => key,
+            _ => continue,
+        };
+        app.message.clear();
+        let mode = std::mem::replace(&mut app.mode, Mode::Browse);
+        app.mode = match mode {
+            Mode::Browse => match browse(&mut app, key) {
+                Some(save) => return Ok(if save { Some(app.hosts_file) } else { None }),
+                None => continue,
+            },
+            Mode::Search => search(&mut app, key),
+            Mode::Edit(form) => edit(&mut app, form, key),
+            Mode::ConfirmQuit => match key.code {
+                KeyCode::Char('y') => return Ok(None),
+                _ => Mode::Browse,
+            },
+        };
+    }
+}
+
+impl App {
+    fn entries(&self) -> Vec<Entry> {
+        self.hosts_file
+            .entries()
+            .into_iter()
+            .filter_map(|(index, enabled)| {
+                let line = self.hosts_file.entry_at(index)?;
+                Some(Entry {
+                    index,
+                    enabled,
+                    ip: line.ip().unwrap_or_default(),
+                    names: line.hosts().join(" "),
+                    comment: line
+                        .comment()
+                        .map(|c| metadata::text(&c))
+                        .unwrap_or_default(),
+                })
+            })
+            .filter(|e| {
+                let text = format!("{} {} {}", e.ip, e.names, e.comment);
+                picker::fuzzy_score(&self.query, &text).is_some()
+            })
+            .collect()
+    }
+
+    fn current(&self) -> Option<Entry> {
+        self.entries().into_iter().nth(self.cursor)
+    }
+
+    fn pending(&self) -> Vec<diff::Hunk> {
+        let contents = self.hosts_file.contents();
+        let old = self.hosts_file.loaded().lines().collect::<Vec<&str>>();
+        let new = contents.lines().collect::<Vec<&str>>();
+        diff::diff_lines(&old, &new)
+    }
+}
+
+/// Handles a key in the entry list. Returns whether to save when the editor
+/// should close.
+fn browse(app: &mut App, key: KeyEvent) -> Option<bool> {
+    let count = app.entries().len();
+    match key.code {
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Some(false),
+        KeyCode::Char('q') | KeyCode::Esc => {
+            if app.pending().is_empty() {
+                return Some(false);
+            }
+            app.mode = Mode::ConfirmQuit;
+        }
+        KeyCode::Char('s') => {
+            if app.pending().is_empty() {
+                app.message = String::from("No pending changes.");
+            } else {
+                return Some(true);
+            }
+        }
+        KeyCode::Up | KeyCode::Char('k') => app.cursor = app.cursor.saturating_sub(1),
+        KeyCode::Down | KeyCode::Char('j') => app.cursor += 1,
+        KeyCode::Home | KeyCode::Char('g') => app.cursor = 0,
+        KeyCode::End | KeyCode::Char('G') => app.cursor = count.saturating_sub(1),
+        KeyCode::Char('/') => app.mode = Mode::Search,
+        KeyCode::Char(' ') | KeyCode::Char('t') => {
+            if let Some(entry) = app.current() {
+                app.hosts_file.toggle_at(entry.index);
+            }
+        }
+        KeyCode::Char('e') | KeyCode::Enter => {
+            if let Some(entry) = app.current() {
+                let comment = app
+                    .hosts_file
+                    .entry_at(entry.index)
+                    .and_then(|l| l.comment())
+                    .unwrap_or_default();
+                app.mode = Mode::Edit(Form {
+                    index: Some(entry.index),
+                    values: [entry.ip, entry.names, entry.comment],
+                    field: 0,
+                    comment,
+                });
+            }
+        }
+        KeyCode::Char('a') => {
+            app.mode = Mode::Edit(Form {
+                index: None,
+                values: [String::from("127.0.0.1"), String::new(), String::new()],
+                field: 1,
+                comment: String::new(),
+            })
+        }
+        KeyCode::Char('d') | KeyCode::Delete => {
+            if let Some(entry) = app.current() {
+                app.hosts_file.remove_at(entry.index);
+                app.message = format!("Removed {}", entry.names);
+            }
+        }
+        KeyCode::Char('u') => {
+            let original = String::from(app.hosts_file.original());
+            app.hosts_file.set_contents(&original);
+            app.message = String::from("Reverted all pending changes.");
+        }
+        _ => {}
+    }
+    app.cursor = app.cursor.min(app.entries().len().saturating_sub(1));
+    None
+}
+
+fn search(app: &mut App, key: KeyEvent) -> Mode {
+    match key.code {
+        KeyCode::Esc => {
+            app.query.clear();
+            return Mode::Browse;
+        }
+        KeyCode::Enter => return Mode::Browse,
+        KeyCode::Backspace => {
+            app.query.pop();
+        }
+        KeyCode::Char(c) => {
+            app.query.push(c);
+            app.cursor = 0;
+        }
+        _ => {}
+    }
+    Mode::Search
+}
+
+fn edit(app: &mut App, mut form: Form, key: KeyEvent) -> Mode {
+    match key.code {
+        KeyCode::Esc => return Mode::Browse,
+        KeyCode::Tab | KeyCode::Down => form.field = (form.field + 1) % FIELDS.len(),
+        KeyCode::BackTab | KeyCode::Up => {
+            form.field = (form.field + FIELDS.len() - 1) % FIELDS.len()
+        }
+        KeyCode::Backspace => {
+            form.values[form.field].pop();
+        }
+        KeyCode::Char(c) => form.values[form.field].push(c),
+        KeyCode::Enter => {
+            let names = form.values[1]
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|n| !n.is_empty())
+                .map(String::from)
+                .collect::<Vec<String>>();
+            let comment = metadata::with_text(&form.comment, &form.values[2]);
+            let comment = if comment.is_empty() {
+                None
+            } else {
+                Some(comment)
+            };
+            match app
+                .hosts_file
+                .put_entry(form.index, form.values[0].trim(), &names, comment)
+            {
+                Ok(index) => {
+                    if let Some(position) = app.entries().iter().position(|e| e.index == index) {
+                        app.cursor = position;
+                    }
+                    return Mode::Browse;
+                }
+                Err(err) => app.message = format!("Error: {}", err),
+            }
+        }
+        _ => {}
+    }
+    Mode::Edit(form)
+}
+
+fn draw(f: &mut Frame, app: &App) {
+    let [header, body, footer] = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Min(3),
+        Constraint::Length(1),
+    ])
+    .areas(f.size());
+    let [list, changes] =
+        Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(body);
+    let hunks = app.pending();
+
+    let mut title = format!("hostman {}", app.hosts_file.file_name());
+    if !hunks.is_empty() {
+        title.push_str(" [modified]");
+    }
+    if !app.query.is_empty() || matches!(app.mode, Mode::Search) {
+        title.push_str(&format!("  search: {}", app.query));
+    }
+    f.render_widget(
+        Paragraph::new(title).style(Style::default().add_modifier(Modifier::BOLD)),
+        header,
+    );
+
+    let entries = app.entries();
+    let rows = entries.iter().map(|e| {
+        let (state, color) = if e.enabled {
+            ("on", Color::Green)
+        } else {
+            ("off", Color::DarkGray)
+        };
+        Row::new(vec![
+            Span::styled(state, Style::default().fg(color)),
+            Span::styled(e.ip.as_str(), Style::default().fg(Color::Blue)),
+            Span::raw(e.names.as_str()),
+            Span::styled(e.comment.as_str(), Style::default().fg(Color::Cyan)),
+        ])
+    });
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(3),
+            Constraint::Length(16),
+            Constraint::Percentage(50),
+            Constraint::Percentage(50),
+        ],
+    )
+    .header(
+        Row::new(vec!["", "ip", "names", "comment"])
+            .style(Style::default().add_modifier(Modifier::BOLD)),
+    )
+    .block(Block::default().borders(Borders::ALL).title("Entries"))
+    .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut state = TableState::default();
+    if !entries.is_empty() {
+        state.select(Some(app.cursor));
+    }
+    f.render_stateful_widget(table, list, &mut state);
+
+    let mut lines = vec![];
+    for hunk in &hunks {
+        lines.push(Line::styled(
+            format!("@@ line {} @@", hunk.start + 1),
+            Style::default().fg(Color::Cyan),
+        ));
+        for line in &hunk.removed {
+            lines.push(Line::styled(
+                format!("- {}", line),
+                Style::default().fg(Color::Red),
+            ));
+        }
+        for line in &hunk.added {
+            lines.push(Line::styled(
+                format!("+ {}", line),
+                Style::default().fg(Color::Green),
+            ));
+        }
+    }
+    f.render_widget(
+        Paragraph::new(lines).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Pending changes"),
+        ),
+        changes,
+    );
+
+    let help = match &app.mode {
+        _ if !app.message.is_empty() => app.message.as_str(),
+        Mode::Browse => "/ search  space toggle  e edit  a add  d delete  u revert  s save  q quit",
+        Mode::Search => "type to filter  enter done  esc clear",
+        Mode::Edit(_) => "tab next field  enter apply  esc cancel",
+        Mode::ConfirmQuit => "Discard pending changes? (y/N)",
+    };
+    f.render_widget(Paragraph::new(help), footer);
+
+    if let Mode::Edit(form) = &app.mode {
+        draw_form(f, form, body);
+    }
+}
+
+fn draw_form(f: &mut Frame, form: &Form, area: Rect) {
+    let width = area.width.saturating_sub(4).min(70);
+    let popup = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + area.height.saturating_sub(5) / 2,
+        width,
+        height: 5.min(area.height),
+    };
+    let lines = FIELDS
+        .iter()
+        .zip(form.values.iter())
+        .enumerate()
+        .map(|(i, (label, value))| {
+            let style = if i == form.field {
+                Style::default().add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            Line::from(vec![
+                Span::styled(format!("{:>8}: ", label), style),
+                Span::raw(value.as_str()),
+            ])
+        })
+        .collect::<Vec<Line>>();
+    let title = if form.index.is_some() {
+        "Edit entry"
+    } else {
+        "Add entry"
+    };
+    f.render_widget(Clear, popup);
+    f.render_widget(
+        Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title)),
+        popup,
+    );
+    f.set_cursor(
+        popup.x + 11 + form.values[form.field].chars().count() as u16,
+        popup.y + 1 + form.field as u16,
+    );
+}
@@ -0,0 +1,208 @@
+use regex::Regex;
+
+/// The host names of a config, one list per server block, and the names that
+/// were skipped with the reason.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Vhosts {
+    pub servers: Vec<Vec<String>>,
+    pub warnings: Vec<String>,
+}
+
+impl Vhosts {
+    fn push(&mut self, names: Vec<String>) {
+        let mut server: Vec<String> = vec![];
+        for name in names {
+            if name.is_empty() || name == "_" || server.contains(&name) {
+                continue;
+            }
+            if name.starts_with('~') {
+                self.warnings
+                    .push(format!("skipping {}: regex names are not supported", name));
+            } else if name.contains(&['*', '?'][..]) || name.starts_with('.') {
+                self.warnings
+                    .push(format!("skipping {}: wildcards are not supported", name));
+            } else if name.contains(&['$', '{'][..]) {
+                self.warnings
+                    .push(format!("skipping {}: variables are not supported", name));
+            } else {
+                server.push(name.to_lowercase());
+            }
+        }
+        if !server.is_empty() {
+            self.servers.push(server);
+        }
+    }
+}
+
+/// The `server_name` directives of an nginx config.
+pub fn nginx(contents: &str) -> Vhosts {
+    let directive = Regex::new(r"(?:^|[\s;{}])server_name\s+([^;]*);").unwrap();
+    let contents = strip_comments(contents);
+    let mut vhosts = Vhosts::default();
+    for c in directive.captures_iter(&contents) {
+        vhosts.push(c[1].split_whitespace().map(String::from).collect());
+    }
+    vhosts
+}
+
+/// The `ServerName` and `ServerAlias` directives of an Apache config, grouped
+/// by `<VirtualHost>`.
+pub fn apache(contents: &str) -> Vhosts {
+    let mut vhosts = Vhosts::default();
+    let mut current: Option<Vec<String>> = None;
+    for line in strip_comments(contents).lines() {
+        let mut words = line.split_whitespace();
+        let directive = words.next().unwrap_or_default().to_lowercase();
+        match directive.as_str() {
+            d if d.starts_with("<virtualhost") => current = Some(vec![]),
+            "</virtualhost>" => vhosts.push(current.take().unwrap_or_default()),
+            "servername" | "serveralias" => {
+                let names = words.map(address_host).collect::<Vec<String>>();
+                match current.as_mut() {
+                    Some(server) => server.extend(names),
+                    None => vhosts.push(names),
+                }
+            }
+            _ => {}
+        }
+    }
+    vhosts
+}
+
+/// The site addresses of a Caddyfile, skipping the global options block,
+/// snippets and port-only addresses.
+pub fn caddy(contents: &str) -> Vhosts {
+    let mut vhosts = Vhosts::default();
+    let mut depth = 0;
+    for line in strip_comments(contents).lines() {
+        let line = line.trim();
+        let top_level = depth == 0
+            && !line.is_empty()
+            && !line.starts_with(&['{', '('][..])
+            && !line.starts_with("import ");
+        if top_level {
+            let addresses = line.strip_suffix('{').unwrap_or(line);
+            vhosts.push(
+                addresses
+                    .split(|c: char| c == ',' || c.is_whitespace())
+                    .map(address_host)
+                    .collect(),
+            );
+        }
+        depth += line.matches('{').count() as i32 - line.matches('}').count() as i32;
+    }
+    vhosts
+}
+
+/// The host of an address like `https://example.test:8443/path`.
+fn address_host(address: &str) -> String {
+    let address = address.trim().trim_matches('"');
+    let address = address.split("://").last().unwrap_or_default();
+    let address = address.split('/').next().unwrap_or_default();
+    if address.starts_with('[') {
+        return String::new();
+    }
+    String::from(address.split(':').next().unwrap_or_default())
+}
+
+fn strip_comments(contents: &str) -> String {
+    contents
+        .lines()
+        .map(|line| match line.find('#') {
+            Some(i) if i == 0 || line[..i].ends_with(char::is_whitespace) => &line[..i],
+            _ => line,
+        })
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(servers: &[&[&str]]) -> Vec<Vec<String>> {
+        servers
+            .iter()
+            .map(|s| s.iter().map(|n| String::from(*n)).collect())
+            .collect()
+    }
+
+    #[test]
+    fn nginx_server_names() {
+        let config = r"
+server {
+    listen 80;
+    server_name app.test www.app.test; # main site
+    # server_name old.test;
+}
+server { server_name _; }
+server {
+    server_name api.test *.api.test ~^(?<user>.+)\.users\.test$
+                .static.test;
+}
+";
+        let vhosts = nginx(config);
+        assert_eq!(
+            vhosts.servers,
+            names(&[&["app.test", "www.app.test"], &["api.test"]])
+        );
+        assert_eq!(vhosts.warnings.len(), 3);
+        assert!(vhosts.warnings[1].starts_with("skipping ~^(?<user>"));
+    }
+
+    #[test]
+    fn apache_server_names() {
+        let config = "
+ServerName main.test:80
+<VirtualHost *:443>
+    ServerName https://app.test
+    ServerAlias www.app.test *.app.test
+</VirtualHost>
+<virtualhost *:80>
+    serveralias alias.test
+</virtualhost>
+";
+        let vhosts = apache(config);
+        assert_eq!(
+            vhosts.servers,
+            names(&[
+                &["main.test"],
+                &["app.test", "www.app.test"],
+                &["alias.test"]
+            ])
+        );
+        assert_eq!(
+            vhosts.warnings,
+            vec!["skipping *.app.test: wildcards are not supported"]
+        );
+    }
+
+    #[test]
+    fn caddy_site_addresses() {
+        let config = "
+{
+    email admin@example.test
+}
+(common) {
+    encode gzip
+}
+app.test, https://www.app.test:8443 {
+    import common
+    reverse_proxy localhost:3000
+    @api host api.inner.test
+}
+:8080 {
+    respond ok
+}
+*.wild.test {$SITE} {
+}
+http://plain.test
+";
+        let vhosts = caddy(config);
+        assert_eq!(
+            vhosts.servers,
+            names(&[&["app.test", "www.app.test"], &["plain.test"]])
+        );
+        assert_eq!(vhosts.warnings.len(), 2);
+    }
+}
@@ -0,0 +1,207 @@
+use crate::entry::Entry;
+use std::net::IpAddr;
+
+const TTL: u32 = 3600;
+
+/// A generated zone file.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Zone {
+    pub name: String,
+    pub text: String,
+}
+
+/// Builds the zone for `origin` from the enabled entries, followed by one
+/// reverse zone per /24 (IPv4) or /64 (IPv6) network when `ptr` is set. Names
+/// outside the origin are left out and reported as warnings.
+pub fn zones(
+    entries: &[Entry],
+    origin: &str,
+    nameserver: Option<&str>,
+    ptr: bool,
+    serial: u32,
+) -> (Vec<Zone>, Vec<String>) {
+    let origin = origin.trim_end_matches('.').to_lowercase();
+    let nameserver = match nameserver {
+        Some(ns) => absolute(ns),
+        None => format!("ns.{}.", origin),
+    };
+    let soa = |name: &str| {
+        vec![
+            String::from("; Generated by hostman, do not edit."),
+            format!("$ORIGIN {}.", name),
+            format!("$TTL {}", TTL),
+            format!(
+                "@ IN SOA {} hostmaster.{}. ({} 3600 900 604800 300)",
+                nameserver, origin, serial
+            ),
+            format!("@ IN NS {}", nameserver),
+        ]
+    };
+
+    let mut warnings = vec![];
+    let mut forward = soa(&origin);
+    let mut reverse: Vec<(String, Vec<String>)> = vec![];
+    let mut pointers: Vec<IpAddr> = vec![];
+    for e in entries.iter().filter(|e| e.enabled) {
+        let ip: IpAddr = match e.ip.parse() {
+            Ok(ip) => ip,
+            Err(_) => {
+                warnings.push(format!("skipping {}: not a plain ip address", e.ip));
+                continue;
+            }
+        };
+        let kind = if ip.is_ipv4() { "A" } else { "AAAA" };
+        for name in &e.names {
+            let label = match relative(name, &origin) {
+                Some(label) => label,
+                None => {
+                    warnings.push(format!("skipping {}: not in {}", name, origin));
+                    continue;
+                }
+            };
+            let record = format!("{} IN {} {}", label, kind, ip);
+            if !forward.contains(&record) {
+                forward.push(record);
+            }
+            if ptr && !pointers.contains(&ip) {
+                pointers.push(ip);
+                let (zone, host) = reverse_name(ip);
+                let record = format!("{} IN PTR {}.", host, name.to_lowercase());
+                match reverse.iter_mut().find(|(z, _)| *z == zone) {
+                    Some((_, records)) => records.push(record),
+                    None => reverse.push((zone, vec![record])),
+                }
+            }
+        }
+    }
+    let has_address = |label: &str| {
+        forward
+            .iter()
+            .any(|r| r.starts_with(&format!("{} IN A", label)))
+    };
+    if relative(&nameserver, &origin).is_some_and(|l| !has_address(&l)) {
+        warnings.push(format!("{} has no address record", nameserver));
+    }
+
+    let mut zones = vec![Zone {
+        name: origin.clone(),
+        text: forward.join("\n") + "\n",
+    }];
+    for (name, records) in reverse {
+        let mut lines = soa(&name);
+        lines.extend(records);
+        zones.push(Zone {
+            name,
+            text: lines.join("\n") + "\n",
+        });
+    }
+    (zones, warnings)
+}
+
+fn absolute(name: &str) -> String {
+    format!("{}.", name.trim_end_matches('.').to_lowercase())
+}
+
+/// Returns the name relative to `origin`, `@` for the origin itself, or `None`
+/// if it is outside the origin.
+fn relative(name: &str, origin: &str) -> Option<String> {
+    let name = name.trim_end_matches('.').to_lowercase();
+    if name == origin {
+        return Some(String::from("@"));
+    }
+    name.strip_suffix(&format!(".{}", origin)).map(String::from)
+}
+
+/// Splits the reverse lookup name of an address into its /24 or /64 zone and
+/// the label within that zone.
+fn reverse_name(ip: IpAddr) -> (String, String) {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, d] = ip.octets();
+            (format!("{}.{}.{}.in-addr.arpa", c, b, a), d.to_string())
+        }
+        IpAddr::V6(ip) => {
+            let nibbles = ip
+                .octets()
+                .iter()
+                .flat_map(|b| vec![b >> 4, b & 0xf])
+                .map(|n| format!("{:x}", n))
+                .collect::<Vec<String>>();
+            let reversed = |part: &[String]| {
+                part.iter()
+                    .rev()
+                    .cloned()
+                    .collect::<Vec<String>>()
+                    .join(".")
+            };
+            (
+                format!("{}.ip6.arpa", reversed(&nibbles[..16])),
+                reversed(&nibbles[16..]),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(ip: &str, names: &[&str]) -> Entry {
+        Entry {
+            ip: String::from(ip),
+            names: names.iter().map(|n| String::from(*n)).collect(),
+            comment: None,
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn forward_zone() {
+        let entries = vec![
+            entry("192.168.1.5", &["www.lab.test", "lab.test", "other.test"]),
+            entry("2001:db8::1", &["www.lab.test"]),
+            entry("fe80::1%lo0", &["scoped.lab.test"]),
+        ];
+        let (zones, warnings) = zones(&entries, "lab.test.", None, false, 1);
+        assert_eq!(zones.len(), 1);
+        assert_eq!(
+            zones[0].text,
+            "; Generated by hostman, do not edit.\n\
+             $ORIGIN lab.test.\n\
+             $TTL 3600\n\
+             @ IN SOA ns.lab.test. hostmaster.lab.test. (1 3600 900 604800 300)\n\
+             @ IN NS ns.lab.test.\n\
+             www IN A 192.168.1.5\n\
+             @ IN A 192.168.1.5\n\
+             www IN AAAA 2001:db8::1\n"
+        );
+        assert_eq!(
+            warnings,
+            vec![
+                "skipping other.test: not in lab.test",
+                "skipping fe80::1%lo0: not a plain ip address",
+                "ns.lab.test. has no address record",
+            ]
+        );
+    }
+
+    #[test]
+    fn reverse_zones() {
+        let entries = vec![
+            entry("192.168.1.5", &["www.lab.test", "web.lab.test"]),
+            entry("192.168.1.6", &["db.lab.test"]),
+            entry("2001:db8::1", &["ns.lab.test"]),
+        ];
+        let (zones, warnings) = zones(&entries, "lab.test", Some("ns.lab.test"), true, 1);
+        assert!(warnings.is_empty());
+        assert_eq!(zones.len(), 3);
+        assert_eq!(zones[1].name, "1.168.192.in-addr.arpa");
+        assert!(zones[1]
+            .text
+            .ends_with("@ IN NS ns.lab.test.\n5 IN PTR www.lab.test.\n6 IN PTR db.lab.test.\n"));
+        assert_eq!(zones[2].name, "0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa");
+        assert!(zones[2]
+            .text
+            .ends_with("\n1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0 IN PTR ns.lab.test.\n"));
+    }
+}